@@ -0,0 +1,180 @@
+use chrono::Utc;
+
+use crate::{
+    api::{AuthData, AuthenticatedUser, OfferSource},
+    cache::{BiedCache, SyncRequest},
+    cli::{AccountsCommand, OffersCommand},
+    db::{BiedStore, StoreError},
+    notify,
+    secrets::CacheConfig,
+    validation::validate_profile,
+};
+
+/// Returns what to print, like the other subcommands.
+/// `profiles` are the names of the configured api profiles.
+pub fn accounts(
+    store: &mut BiedStore,
+    command: AccountsCommand,
+    profiles: &[String],
+) -> Result<String, StoreError> {
+    Ok(match command {
+        AccountsCommand::List => store
+            .fetch_accounts()
+            .into_iter()
+            .map(|(title, user)| {
+                format!(
+                    "{title}\t{}\t{}\t{}",
+                    user.card_number, user.phone_number, user.profile
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        AccountsCommand::Add {
+            title,
+            card_number,
+            phone_number,
+            users1,
+            users2,
+            csrf_token,
+            profile,
+        } => {
+            validate_profile(&profile, profiles)?;
+            store.insert_account(
+                &title,
+                AuthenticatedUser {
+                    phone_number,
+                    card_number,
+                    auth: AuthData {
+                        users1,
+                        users2,
+                        csrf_token,
+                        cookies: Default::default(),
+                    },
+                    profile,
+                },
+            )?;
+            format!("Added account {title}")
+        }
+        AccountsCommand::Remove { title } => {
+            store.remove_account(&title)?;
+            format!("Removed account {title}")
+        }
+        AccountsCommand::Rename { old, new } => {
+            store.rename_account(&old, &new)?;
+            format!("Renamed account {old} to {new}")
+        }
+        AccountsCommand::Profile { title, profile } => {
+            validate_profile(&profile, profiles)?;
+            store.set_profile(&title, &profile)?;
+            format!("Set the profile of {title} to {profile}")
+        }
+    })
+}
+
+pub async fn sync(
+    store: &mut BiedStore,
+    api: &dyn OfferSource,
+    cache_config: &CacheConfig,
+    dry_run: bool,
+) -> Result<String, StoreError> {
+    let cache = BiedCache::new(cache_config);
+    let report = cache
+        .sync_offers(store.fetch_accounts(), api, SyncRequest::default())
+        .await;
+    // the rotated cookies are saved even on a dry run, the old ones may be invalid already
+    store.save_cookies(&report.cookies)?;
+    let mut lines = Vec::new();
+    if dry_run {
+        for (title, offers) in &cache.snapshot().offers {
+            lines.push(format!("== {title} =="));
+            lines.extend(offers.iter().map(|o| format!("{o}\n")));
+        }
+    } else {
+        notify::queue_offer_alerts(store, &cache.snapshot(), Utc::now())?;
+    }
+    lines.push(report.to_string());
+    Ok(lines.join("\n"))
+}
+
+pub async fn offers(
+    store: &mut BiedStore,
+    api: &dyn OfferSource,
+    cache_config: &CacheConfig,
+    command: OffersCommand,
+) -> Result<String, StoreError> {
+    Ok(match command {
+        OffersCommand::Show { title } => {
+            let user = store.fetch_account(&title)?;
+            let cache = BiedCache::new(cache_config);
+            let report = cache
+                .sync_offers(vec![(title.clone(), user)], api, SyncRequest::default())
+                .await;
+            store.save_cookies(&report.cookies)?;
+            let snapshot = cache.snapshot();
+            let offers = snapshot.get_offers(&title).into_iter().flatten();
+            offers
+                .map(|o| format!("{o}\n"))
+                .chain([report.to_string()])
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fake::{account, FakeResponse, FakeSource, OFFERS_FIXTURE},
+        secrets::DEFAULT_PROFILE,
+        validation::ValidationError,
+    };
+
+    #[test]
+    fn adds_accounts_of_configured_profiles_only() {
+        let mut store = BiedStore::temporary();
+        let add = |profile: &str| AccountsCommand::Add {
+            title: "anna".to_string(),
+            card_number: "5901234123457".to_string(),
+            phone_number: "500600700".to_string(),
+            users1: "users1".to_string(),
+            users2: "users2".to_string(),
+            csrf_token: "a".to_string(),
+            profile: profile.to_string(),
+        };
+        let profiles = [DEFAULT_PROFILE.to_string()];
+
+        let result = accounts(&mut store, add("other"), &profiles);
+        assert!(matches!(
+            result,
+            Err(StoreError::Invalid(ValidationError::UnknownProfile(..)))
+        ));
+        assert!(store.fetch_accounts().is_empty());
+
+        accounts(&mut store, add(DEFAULT_PROFILE), &profiles).unwrap();
+        let listed = accounts(&mut store, AccountsCommand::List, &profiles).unwrap();
+        assert_eq!(listed, "anna\t5901234123457\t+48500600700\tdefault");
+    }
+
+    #[tokio::test]
+    async fn shows_the_offers_of_the_named_account() {
+        let mut store = BiedStore::temporary();
+        store.insert_account("jan", account("jan")).unwrap();
+        store.insert_account("anna", account("anna")).unwrap();
+        let api = FakeSource::default()
+            .with("jan", FakeResponse::Offers(OFFERS_FIXTURE.to_string()))
+            .with("anna", FakeResponse::Network);
+
+        let command = OffersCommand::Show {
+            title: "jan".to_string(),
+        };
+        let shown = offers(&mut store, &api, &CacheConfig::default(), command)
+            .await
+            .unwrap();
+        assert_eq!(api.calls(), 1);
+        assert!(shown.starts_with("Mleko UHT 3,2%\n"));
+        assert!(shown.contains("Kawa ziarnista"));
+        assert!(shown.contains("jan"));
+        assert!(!shown.contains("anna"));
+    }
+}
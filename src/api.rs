@@ -1,21 +1,220 @@
-use std::fmt::Display;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    time::Duration,
+};
 
-use reqwest::{header, Client, RequestBuilder};
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use lazy_static::lazy_static;
+use regex::Regex;
+use reqwest::{
+    header::{self, HeaderMap, HeaderName, HeaderValue},
+    Client, Proxy, RequestBuilder, Response, StatusCode,
+};
 use serde::{Deserialize, Serialize};
 
-use crate::secrets::ApiConfig;
+use crate::{
+    cookies::CookieJar,
+    filter::parse_price,
+    i18n::{escape_html, fill, Lang, Localize},
+    secrets::ApiConfig,
+};
 
+/// Longest wait between two retries of a request.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Where offers come from, so the cache and handlers don't depend on the real upstream.
+#[async_trait]
+pub trait OfferSource: Send + Sync {
+    /// Updates the cookies of `auth` with the ones set by the server.
+    async fn get_offers(&self, profile: &str, auth: &mut AuthData) -> Result<Vec<Offer>, ApiError>;
+
+    async fn get_account_summary(
+        &self,
+        profile: &str,
+        auth: &mut AuthData,
+    ) -> Result<AccountSummary, ApiError>;
+
+    /// Receipts of the card, starting at `since` when given.
+    async fn get_transactions(
+        &self,
+        profile: &str,
+        auth: &mut AuthData,
+        since: Option<NaiveDate>,
+    ) -> Result<Vec<Transaction>, ApiError>;
+
+    /// Activates a personalised offer, so it applies at the till.
+    async fn activate_offer(
+        &self,
+        profile: &str,
+        auth: &mut AuthData,
+        offer_id: &str,
+    ) -> Result<(), ApiError>;
+}
+
+/// Clients of every api profile, as accounts of different brands talk to different api roots.
 pub struct BiedApi {
+    profiles: HashMap<String, ApiClient>,
+}
+
+struct ApiClient {
     config: ApiConfig,
     client: Client,
+    headers: HeaderMap,
+}
+
+#[async_trait]
+impl OfferSource for BiedApi {
+    async fn get_offers(&self, profile: &str, auth: &mut AuthData) -> Result<Vec<Offer>, ApiError> {
+        self.profile(profile)?.get_offers(auth).await
+    }
+
+    async fn activate_offer(
+        &self,
+        profile: &str,
+        auth: &mut AuthData,
+        offer_id: &str,
+    ) -> Result<(), ApiError> {
+        self.profile(profile)?.activate_offer(auth, offer_id).await
+    }
+
+    async fn get_account_summary(
+        &self,
+        profile: &str,
+        auth: &mut AuthData,
+    ) -> Result<AccountSummary, ApiError> {
+        self.profile(profile)?.get_account_summary(auth).await
+    }
+
+    async fn get_transactions(
+        &self,
+        profile: &str,
+        auth: &mut AuthData,
+        since: Option<NaiveDate>,
+    ) -> Result<Vec<Transaction>, ApiError> {
+        self.profile(profile)?.get_transactions(auth, since).await
+    }
 }
 
 impl BiedApi {
+    fn profile(&self, profile: &str) -> Result<&ApiClient, ApiError> {
+        self.profiles
+            .get(profile)
+            .ok_or_else(|| ApiError::Config(format!("unknown api profile `{profile}`")))
+    }
+
+    pub fn new(profiles: BTreeMap<String, ApiConfig>) -> Result<Self, ApiError> {
+        Ok(Self {
+            profiles: profiles
+                .into_iter()
+                .map(|(name, config)| Ok((name, ApiClient::new(config)?)))
+                .collect::<Result<_, ApiError>>()?,
+        })
+    }
+}
+
+pub fn parse_offers(body: &str) -> Result<Vec<Offer>, ApiError> {
+    let res: BiedApiResponce<OfferResponce> = serde_json::from_str(body)?;
+    Ok(res
+        .data
+        .j4y
+        .list
+        .into_iter()
+        .map(|e| e.into())
+        .filter(|e: &Offer| !e.name.is_empty())
+        .collect())
+}
+
+pub fn parse_account_summary(body: &str) -> Result<AccountSummary, ApiError> {
+    let res: BiedApiResponce<AccountResponce> = serde_json::from_str(body)?;
+    Ok(res.data.loyalty_account.into())
+}
+
+pub fn parse_transactions(body: &str) -> Result<Vec<Transaction>, ApiError> {
+    let res: BiedApiResponce<HistoryResponce> = serde_json::from_str(body)?;
+    res.data
+        .transactions
+        .list
+        .into_iter()
+        .map(Transaction::try_from)
+        .collect()
+}
+
+impl ApiClient {
+    //TODO: Allow for image only offers
+    async fn get_offers(&self, auth: &mut AuthData) -> Result<Vec<Offer>, ApiError> {
+        let request = self.api_rq(
+            &format!("{}_Sync/ActionServerDataSync_2_J4y", self.config.brand_name),
+            &self.config.promo_sync_api_version,
+            auth,
+            &OfferRequest {
+                j4y_cache_refresh: "2022-01-01T10:10:10.101Z".to_string(),
+            },
+        )?;
+        let body = self.send(request, auth).await?.text().await?;
+        parse_offers(&body)
+    }
+
+    async fn activate_offer(&self, auth: &mut AuthData, offer_id: &str) -> Result<(), ApiError> {
+        let request = self.api_rq(
+            &format!("{}_Sync/ActionActivateJ4yOffer", self.config.brand_name),
+            self.api_version(&self.config.activate_offer_api_version),
+            auth,
+            &ActivateRequest {
+                offer_id_ext: offer_id.to_string(),
+            },
+        )?;
+        // activating isn't idempotent, a timed out attempt may have gone through
+        self.send_retrying(request, auth, 0).await?;
+        Ok(())
+    }
+
+    async fn get_account_summary(&self, auth: &mut AuthData) -> Result<AccountSummary, ApiError> {
+        let request = self.api_rq(
+            &format!("{}_Sync/ActionGetLoyaltyAccount", self.config.brand_name),
+            self.api_version(&self.config.account_api_version),
+            auth,
+            &EmptyRequest {},
+        )?;
+        let body = self.send(request, auth).await?.text().await?;
+        parse_account_summary(&body)
+    }
+
+    async fn get_transactions(
+        &self,
+        auth: &mut AuthData,
+        since: Option<NaiveDate>,
+    ) -> Result<Vec<Transaction>, ApiError> {
+        let request = self.api_rq(
+            &format!(
+                "{}_Sync/ActionGetTransactionHistory",
+                self.config.brand_name
+            ),
+            self.api_version(&self.config.history_api_version),
+            auth,
+            &HistoryRequest {
+                from_date: since
+                    .map(|d| d.format("%Y-%m-%d").to_string())
+                    .unwrap_or_default(),
+            },
+        )?;
+        let body = self.send(request, auth).await?.text().await?;
+        parse_transactions(&body)
+    }
+
+    /// Version of an optional endpoint, falling back to the offer sync one.
+    fn api_version<'a>(&'a self, version: &'a Option<String>) -> &'a str {
+        version
+            .as_deref()
+            .unwrap_or(&self.config.promo_sync_api_version)
+    }
+
     fn api_rq<T>(
         &self,
         url: &str,
         api_version: &str,
-        auth: AuthData,
+        auth: &AuthData,
         data: &T,
     ) -> Result<RequestBuilder, ApiError>
     where
@@ -33,90 +232,164 @@ impl BiedApi {
                 input_parameters: data,
             })?)
             .header(header::CONTENT_TYPE, "application/json; charset=UTF-8")
-            .header("x-csrftoken", auth.csrf_token)
-            .header(
-                "cookie",
-                format!("nr1Users={}; nr2Users={};", auth.users1, auth.users2),
-            ))
+            .headers(self.headers.clone())
+            .header("x-csrftoken", &auth.csrf_token))
     }
 
-    //TODO: Allow for image only offers
-    pub async fn get_offers(&self, auth: AuthData) -> Result<Vec<Offer>, ApiError> {
-        let res: BiedApiResponce<OfferResponce> = self
-            .api_rq(
-                &format!("{}_Sync/ActionServerDataSync_2_J4y", self.config.brand_name),
-                &self.config.promo_sync_api_version,
-                auth,
-                &OfferRequest {
-                    j4y_cache_refresh: "2022-01-01T10:10:10.101Z".to_string(),
-                },
-            )?
-            .send()
-            .await?
-            .json()
-            .await?;
-
-        Ok(res
-            .data
-            .j4y
-            .list
-            .into_iter()
-            .map(|e| e.into())
-            .filter(|e: &Offer| !e.name.is_empty())
-            .collect())
+    /// Sends the request with the current cookies of the account, retrying timeouts,
+    /// connection errors and 5xx/429 responses.
+    async fn send(
+        &self,
+        request: RequestBuilder,
+        auth: &mut AuthData,
+    ) -> Result<Response, ApiError> {
+        self.send_retrying(request, auth, self.config.http.max_retries)
+            .await
     }
 
-    pub fn new(config: ApiConfig) -> Self {
-        Self {
-            config,
-            client: reqwest::Client::new(),
+    async fn send_retrying(
+        &self,
+        request: RequestBuilder,
+        auth: &mut AuthData,
+        max_retries: u32,
+    ) -> Result<Response, ApiError> {
+        let http = &self.config.http;
+        let mut backoff = Duration::from_millis(http.retry_backoff_ms).min(MAX_BACKOFF);
+        let mut attempt = 0;
+        loop {
+            let res = request
+                .try_clone()
+                .ok_or_else(|| ApiError::Config("request can't be retried".to_string()))?
+                .header(header::COOKIE, auth.cookie_header())
+                .send()
+                .await;
+            if let Ok(r) = &res {
+                auth.cookies.update(r.headers());
+            }
+            let retryable = match &res {
+                Ok(r) => {
+                    r.status().is_server_error() || r.status() == StatusCode::TOO_MANY_REQUESTS
+                }
+                Err(e) => e.is_timeout() || e.is_connect(),
+            };
+            if !retryable || attempt >= max_retries {
+                return res.map_err(ApiError::from).and_then(check_status);
+            }
+            attempt += 1;
+            log::warn!("api request failed, retrying in {backoff:?} ({attempt}/{max_retries})");
+            tokio::time::sleep(backoff).await;
+            backoff = backoff.saturating_mul(2).min(MAX_BACKOFF);
+        }
+    }
+
+    pub fn new(config: ApiConfig) -> Result<Self, ApiError> {
+        let http = &config.http;
+        let mut client = Client::builder()
+            .connect_timeout(Duration::from_secs(http.connect_timeout_secs))
+            .timeout(Duration::from_secs(http.request_timeout_secs));
+        if let Some(proxy) = &http.proxy {
+            client = client.proxy(
+                Proxy::all(proxy)
+                    .map_err(|e| ApiError::Config(format!("invalid proxy `{proxy}`: {e}")))?,
+            );
         }
+        let headers = http
+            .headers
+            .iter()
+            .map(|(k, v)| {
+                Ok((
+                    HeaderName::from_bytes(k.as_bytes())
+                        .map_err(|_| ApiError::Config(format!("invalid header name `{k}`")))?,
+                    HeaderValue::from_str(v)
+                        .map_err(|_| ApiError::Config(format!("invalid value of header `{k}`")))?,
+                ))
+            })
+            .collect::<Result<HeaderMap, ApiError>>()?;
+        Ok(Self {
+            client: client
+                .build()
+                .map_err(|e| ApiError::Config(format!("{e}")))?,
+            headers,
+            config,
+        })
+    }
+}
+
+fn check_status(res: Response) -> Result<Response, ApiError> {
+    match res.status() {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(ApiError::AuthExpired),
+        s if !s.is_success() => Err(ApiError::Network(format!("HTTP {s}"))),
+        _ => Ok(res),
     }
 }
 
 #[derive(Debug)]
-pub struct ApiError(String);
+pub enum ApiError {
+    AuthExpired,
+    Network(String),
+    Decode(String),
+    Config(String),
+}
+
+/// The details come from the http client and the api, so they stay in English.
+impl Localize for ApiError {
+    fn localize(&self, lang: Lang) -> String {
+        let texts = lang.texts();
+        match self {
+            ApiError::AuthExpired => texts.auth_expired.to_string(),
+            ApiError::Network(e) => fill(texts.network_error, &[e]),
+            ApiError::Decode(e) => fill(texts.decode_error, &[e]),
+            ApiError::Config(e) => fill(texts.api_config_error, &[e]),
+        }
+    }
+}
+
+impl Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.localize(Lang::En))
+    }
+}
 
 impl From<reqwest::Error> for ApiError {
     fn from(e: reqwest::Error) -> Self {
-        ApiError(format!("{:?}", e))
+        if e.is_decode() {
+            ApiError::Decode(format!("{:?}", e))
+        } else {
+            ApiError::Network(format!("{:?}", e))
+        }
     }
 }
 
 impl From<serde_json::Error> for ApiError {
     fn from(e: serde_json::Error) -> Self {
-        ApiError(format!("{:?}", e))
-    }
-}
-
-impl From<cookie::ParseError> for ApiError {
-    fn from(e: cookie::ParseError) -> Self {
-        ApiError(format!("{:?}", e))
+        ApiError::Decode(format!("{:?}", e))
     }
 }
 
-impl Into<Offer> for OfferElement {
-    fn into(self) -> Offer {
+impl From<OfferElement> for Offer {
+    fn from(e: OfferElement) -> Self {
         Offer {
-            id: self.offer_id_ext,
-            name: self.name,
-            details: format!(
-                "{};{}\n{};{}",
-                self.description, self.promo_details, self.tag_top_line, self.tag_bottom_line
-            ),
-            limit: self.limits,
-            image: vec![self.full_screen_image_url, self.image_url, self.thumb_url]
+            id: e.offer_id_ext,
+            name: e.name,
+            details: format!("{};{}", e.description, e.promo_details),
+            tags: [e.tag_top_line, e.tag_bottom_line]
                 .into_iter()
                 .filter(|e| !e.is_empty())
-                .collect::<Vec<_>>()
-                .first()
-                .map(|e| e.to_string()),
-            human_time: self.promotion_time,
-            regular_price: self.regular_price,
-            regular_price_unit: self.regular_price_per_unit,
-            offer_price: self.promo_price,
-            offer_price_unit: self.price_per_unit,
-            discount_percent: self.discount,
+                .collect(),
+            offer_type: e.offer_type,
+            product_url: Some(e.product_url).filter(|e| !e.is_empty()),
+            limit: e.limits,
+            // largest first
+            image: [e.full_screen_image_url, e.image_url, e.thumb_url.clone()]
+                .into_iter()
+                .find(|e| !e.is_empty()),
+            thumb_url: Some(e.thumb_url).filter(|e| !e.is_empty()),
+            human_time: e.promotion_time,
+            regular_price: e.regular_price,
+            regular_price_unit: e.regular_price_per_unit,
+            offer_price: e.promo_price,
+            offer_price_unit: e.price_per_unit,
+            discount_percent: e.discount,
         }
     }
 }
@@ -125,9 +398,10 @@ impl Display for Offer {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "{}\n{}\n{} -> {}\n{} -> {}\n{}",
+            "{}\n{}\n{}\n{} -> {}\n{} -> {}\n{}",
             self.name,
             self.details,
+            self.tags.join(";"),
             self.regular_price,
             self.offer_price,
             self.regular_price_unit,
@@ -138,21 +412,57 @@ impl Display for Offer {
 }
 
 impl Offer {
+    /// Id of the offer in the api, needed to activate it.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
     pub fn short_display(&self) -> String {
         format!(
             "{} - {} => {}",
             self.name, self.regular_price_unit, self.offer_price_unit
         )
     }
+
+    /// Offer price in grosze, if it can be parsed.
+    pub fn price(&self) -> Option<u32> {
+        parse_price(&self.offer_price)
+    }
+
+    pub fn html_display(&self) -> String {
+        format!(
+            "<b>{}</b>\n<code>{}</code>\n<code>{}</code>\n{}",
+            escape_html(&self.name),
+            escape_html(&self.details),
+            escape_html(&self.tags.join(";")),
+            escape_html(&format!(
+                "{}\n{} -> {}\n{} -> {}",
+                self.limit,
+                self.regular_price,
+                self.offer_price,
+                self.regular_price_unit,
+                self.offer_price_unit
+            ))
+        )
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Offer {
     id: String,
     pub name: String,
+    /// Description and conditions, e.g. `1 l;przy zakupie 2 szt.`.
     pub details: String,
+    /// Labels like `Tylko z kartą`.
+    pub tags: Vec<String>,
+    /// `J4Y` for personal offers.
+    pub offer_type: String,
+    pub product_url: Option<String>,
     pub limit: String,
+    /// The largest image of the offer, relative to the cdn root.
     pub image: Option<String>,
+    /// Small image for previews like inline results.
+    pub thumb_url: Option<String>,
     pub human_time: String,
     pub regular_price: String,
     pub regular_price_unit: String,
@@ -161,6 +471,35 @@ pub struct Offer {
     pub discount_percent: i32,
 }
 
+/// A receipt of the card, amounts are in grosze and negative for refunds.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Transaction {
+    pub id: String,
+    pub date: NaiveDate,
+    pub store: String,
+    pub total: i32,
+    /// Saved thanks to promotions.
+    pub savings: i32,
+}
+
+/// Points balance and profile data of a card.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AccountSummary {
+    pub points: i64,
+    pub tier: String,
+    pub first_name: String,
+    pub email: String,
+}
+
+impl AccountSummary {
+    pub fn render(&self, lang: Lang) -> String {
+        fill(
+            lang.texts().summary,
+            &[&self.points, &self.tier, &self.first_name, &self.email],
+        )
+    }
+}
+
 impl Display for AuthenticatedUser {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -176,6 +515,9 @@ pub struct AuthenticatedUser {
     pub phone_number: String,
     pub card_number: String,
     pub auth: AuthData,
+    /// Name of the api profile the card belongs to, stored separately by `BiedStore`.
+    #[serde(skip)]
+    pub profile: String,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -183,6 +525,25 @@ pub struct AuthData {
     pub users1: String,
     pub users2: String,
     pub csrf_token: String,
+    /// Stored separately by `BiedStore`.
+    #[serde(skip)]
+    pub cookies: CookieJar,
+}
+
+impl AuthData {
+    /// Cookies set by the server take precedence over the ones the account was added with.
+    pub fn cookie_header(&self) -> String {
+        let mut cookies = BTreeMap::from([
+            ("nr1Users", self.users1.as_str()),
+            ("nr2Users", self.users2.as_str()),
+        ]);
+        cookies.extend(self.cookies.iter());
+        cookies
+            .into_iter()
+            .map(|(name, value)| format!("{name}={value}"))
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
 }
 
 #[derive(Deserialize)]
@@ -197,6 +558,110 @@ struct OfferRequest {
     j4y_cache_refresh: String,
 }
 
+#[derive(Serialize)]
+struct EmptyRequest {}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct AccountResponce {
+    loyalty_account: LoyaltyAccountElement,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct LoyaltyAccountElement {
+    points_balance: i64,
+    tier_name: String,
+    first_name: String,
+    email: String,
+}
+
+impl From<LoyaltyAccountElement> for AccountSummary {
+    fn from(e: LoyaltyAccountElement) -> Self {
+        AccountSummary {
+            points: e.points_balance,
+            tier: e.tier_name,
+            first_name: e.first_name,
+            email: e.email,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct HistoryRequest {
+    /// `YYYY-MM-DD`, empty for the whole history.
+    #[serde(rename = "FromDate")]
+    from_date: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct HistoryResponce {
+    transactions: BiedListWrapper<TransactionElement>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct TransactionElement {
+    transaction_id: String,
+    transaction_date: String,
+    store_name: String,
+    total_amount: String,
+    promo_savings: String,
+}
+
+impl TryFrom<TransactionElement> for Transaction {
+    type Error = ApiError;
+
+    fn try_from(e: TransactionElement) -> Result<Self, Self::Error> {
+        let amount = |a: &str| {
+            parse_amount(a).ok_or_else(|| ApiError::Decode(format!("invalid amount `{a}`")))
+        };
+        Ok(Transaction {
+            date: NaiveDate::parse_from_str(&e.transaction_date, "%Y-%m-%d")
+                .map_err(|_| ApiError::Decode(format!("invalid date `{}`", e.transaction_date)))?,
+            total: amount(&e.total_amount)?,
+            savings: amount(&e.promo_savings)?,
+            id: e.transaction_id,
+            store: e.store_name,
+        })
+    }
+}
+
+lazy_static! {
+    static ref AMOUNT: Regex =
+        Regex::new(r"^(-)?(\d{1,3}(?:\.\d{3})+|\d+)(?:[.,](\d{1,2}))?$").unwrap();
+}
+
+/// Parses a receipt amount into grosze, e.g. `"1 234,56 zł"` -> `123456` or `"-12,5"` -> `-1250`.
+fn parse_amount(amount: &str) -> Option<i32> {
+    let compact = amount
+        .trim()
+        .trim_end_matches("zł")
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>();
+    let caps = AMOUNT.captures(&compact)?;
+    let whole: i32 = caps[2].replace('.', "").parse().ok()?;
+    let fraction = match caps.get(3).map(|e| e.as_str()) {
+        Some(f) if f.len() == 1 => f.parse::<i32>().ok()? * 10,
+        Some(f) => f.parse().ok()?,
+        None => 0,
+    };
+    let grosze = whole.checked_mul(100)?.checked_add(fraction)?;
+    Some(if caps.get(1).is_some() {
+        -grosze
+    } else {
+        grosze
+    })
+}
+
+#[derive(Serialize)]
+struct ActivateRequest {
+    #[serde(rename = "OfferIdExt")]
+    offer_id_ext: String,
+}
+
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 struct BiedApiRequest<T> {
@@ -238,6 +703,7 @@ struct OfferElement {
     full_screen_image_url: String,
 }
 
+#[allow(dead_code)]
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct VersionInfoResponce {
@@ -245,6 +711,7 @@ struct VersionInfoResponce {
     has_api_version_changed: bool,
 }
 
+#[allow(dead_code)]
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct BiedApiResponce<T> {
@@ -257,3 +724,195 @@ struct BiedApiResponce<T> {
 struct BiedListWrapper<T> {
     list: Vec<T>,
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use axum::{http::HeaderMap, routing::post, Extension, Router};
+
+    use super::*;
+    use crate::{
+        fake::{account, ACCOUNT_FIXTURE, OFFERS_FIXTURE},
+        secrets::HttpConfig,
+    };
+
+    #[test]
+    fn converts_offer_elements() {
+        let offers = parse_offers(OFFERS_FIXTURE).unwrap();
+        assert_eq!(offers.len(), 2, "image only offers are skipped");
+
+        let milk = &offers[0];
+        assert_eq!(milk.id, "1001");
+        assert_eq!(milk.name, "Mleko UHT 3,2%");
+        assert_eq!(milk.details, "1 l;przy zakupie 2 szt.");
+        assert_eq!(milk.tags, ["Tylko z kartą", "Twoja oferta"]);
+        assert_eq!(milk.offer_type, "J4Y");
+        assert_eq!(
+            milk.product_url.as_deref(),
+            Some("https://example.com/p/1001")
+        );
+        assert_eq!(milk.image.as_deref(), Some("/image/1001.jpg"));
+        assert_eq!(milk.thumb_url.as_deref(), Some("/thumb/1001.jpg"));
+        assert_eq!(milk.discount_percent, 30);
+        assert_eq!(milk.price(), Some(249));
+
+        assert_eq!(offers[1].image, None);
+        assert_eq!(offers[1].thumb_url, None);
+        assert_eq!(offers[1].product_url, None);
+    }
+
+    #[test]
+    fn renders_offers() {
+        let offers = parse_offers(OFFERS_FIXTURE).unwrap();
+        assert_eq!(
+            offers[0].to_string(),
+            "Mleko UHT 3,2%\n1 l;przy zakupie 2 szt.\nTylko z kartą;Twoja oferta\n3,59 -> 2,49\n3,59 zł/l -> 2,49 zł/l\nmax 6 szt."
+        );
+        assert_eq!(
+            offers[1].short_display(),
+            "Kawa ziarnista - 59,99 zł/kg => 39,99 zł/kg"
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_unknown_profiles() {
+        let api = BiedApi::new(BTreeMap::new()).unwrap();
+        let mut auth = account("a").auth;
+        assert!(matches!(
+            api.get_offers("other", &mut auth).await,
+            Err(ApiError::Config(_))
+        ));
+    }
+
+    /// Client of a local server running `app` under the `Test` brand.
+    fn serve(app: axum::Router) -> ApiClient {
+        let server =
+            axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(app.into_make_service());
+        let address = server.local_addr();
+        tokio::spawn(server);
+
+        ApiClient::new(ApiConfig {
+            api_root: format!("http://{address}/"),
+            brand_name: "Test".to_string(),
+            module_version: "1".to_string(),
+            promo_sync_api_version: "1".to_string(),
+            activate_offer_api_version: None,
+            account_api_version: None,
+            history_api_version: None,
+            http: HttpConfig {
+                retry_backoff_ms: 1,
+                ..Default::default()
+            },
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors_with_rotated_cookies() {
+        // fails twice, rotating the session cookie, before answering
+        async fn flaky(
+            Extension(hits): Extension<Arc<AtomicUsize>>,
+            headers: HeaderMap,
+        ) -> (
+            StatusCode,
+            [(header::HeaderName, &'static str); 1],
+            &'static str,
+        ) {
+            let rotated = headers[header::COOKIE]
+                .to_str()
+                .unwrap()
+                .contains("nr1Users=rotated");
+            match hits.fetch_add(1, Ordering::SeqCst) {
+                0 | 1 => (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    [(header::SET_COOKIE, "nr1Users=rotated; Path=/")],
+                    "",
+                ),
+                _ if !rotated => (
+                    StatusCode::UNAUTHORIZED,
+                    [(header::SET_COOKIE, "session=none")],
+                    "",
+                ),
+                _ => (
+                    StatusCode::OK,
+                    [(header::SET_COOKIE, "session=abc")],
+                    OFFERS_FIXTURE,
+                ),
+            }
+        }
+        let hits = Arc::new(AtomicUsize::new(0));
+        let api = serve(
+            Router::new()
+                .route("/Test_Sync/ActionServerDataSync_2_J4y", post(flaky))
+                .layer(Extension(hits.clone())),
+        );
+
+        let mut auth = account("token").auth;
+        let offers = api.get_offers(&mut auth).await.unwrap();
+        assert_eq!(offers.len(), 2);
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+        assert_eq!(
+            auth.cookie_header(),
+            "nr1Users=rotated; nr2Users=users2; session=abc"
+        );
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_activations() {
+        async fn unavailable(Extension(hits): Extension<Arc<AtomicUsize>>) -> StatusCode {
+            hits.fetch_add(1, Ordering::SeqCst);
+            StatusCode::SERVICE_UNAVAILABLE
+        }
+        let hits = Arc::new(AtomicUsize::new(0));
+        let api = serve(
+            Router::new()
+                .route("/Test_Sync/ActionActivateJ4yOffer", post(unavailable))
+                .layer(Extension(hits.clone())),
+        );
+
+        let mut auth = account("token").auth;
+        assert!(matches!(
+            api.activate_offer(&mut auth, "1001").await,
+            Err(ApiError::Network(_))
+        ));
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn parses_account_summaries() {
+        let summary = parse_account_summary(ACCOUNT_FIXTURE).unwrap();
+        assert_eq!(summary.points, 1250);
+        assert_eq!(
+            summary.render(Lang::En),
+            "1250 points, Gold tier (Anna, anna@example.com)"
+        );
+        assert_eq!(
+            summary.render(Lang::Pl),
+            "1250 pkt, poziom Gold (Anna, anna@example.com)"
+        );
+    }
+
+    #[test]
+    fn parses_receipt_amounts() {
+        assert_eq!(parse_amount("87,15"), Some(8715));
+        assert_eq!(parse_amount("1 234,56"), Some(123456));
+        assert_eq!(parse_amount("1\u{a0}234,56 zł"), Some(123456));
+        assert_eq!(parse_amount("1.234,5"), Some(123450));
+        assert_eq!(parse_amount("-12,50"), Some(-1250));
+        assert_eq!(parse_amount("12.5"), Some(1250));
+        assert_eq!(parse_amount("12,50 PLN"), None);
+        assert_eq!(parse_amount("99999999"), None);
+    }
+
+    #[test]
+    fn reports_malformed_responses_as_decode_errors() {
+        assert!(matches!(
+            parse_offers("{\"data\": {}}"),
+            Err(ApiError::Decode(_))
+        ));
+    }
+}
@@ -0,0 +1,186 @@
+use png::{BitDepth, ColorType, Encoder};
+use qrcode::{Color, QrCode};
+
+use crate::i18n::{fill, Lang, Localize};
+
+const EAN13_L: [&str; 10] = [
+    "0001101", "0011001", "0010011", "0111101", "0100011", "0110001", "0101111", "0111011",
+    "0110111", "0001011",
+];
+const EAN13_G: [&str; 10] = [
+    "0100111", "0110011", "0011011", "0100001", "0011101", "0111001", "0000101", "0010001",
+    "0001001", "0010111",
+];
+const EAN13_R: [&str; 10] = [
+    "1110010", "1100110", "1101100", "1000010", "1011100", "1001110", "1010000", "1000100",
+    "1001000", "1110100",
+];
+// parity of the left half digits, selected by the first digit
+const EAN13_PARITY: [&str; 10] = [
+    "LLLLLL", "LLGLGG", "LLGGLG", "LLGGGL", "LGLLGG", "LGGLLG", "LGGGLL", "LGLGLG", "LGLGGL",
+    "LGGLGL",
+];
+
+const EAN13_MODULE_PX: usize = 4;
+const EAN13_QUIET_MODULES: usize = 11;
+const EAN13_HEIGHT_PX: usize = 240;
+const QR_MODULE_PX: usize = 10;
+const QR_QUIET_MODULES: usize = 4;
+
+pub fn ean13_check_digit(digits: &[u8]) -> u8 {
+    let sum: u32 = digits
+        .iter()
+        .take(12)
+        .enumerate()
+        .map(|(i, d)| *d as u32 * if i % 2 == 0 { 1 } else { 3 })
+        .sum();
+    ((10 - sum % 10) % 10) as u8
+}
+
+pub fn parse_ean13(code: &str) -> Result<[u8; 13], BarcodeError> {
+    let code = code.trim();
+    if code.chars().count() != 13 {
+        return Err(BarcodeError::Length(code.chars().count()));
+    }
+    let mut digits = [0u8; 13];
+    for (d, c) in digits.iter_mut().zip(code.chars()) {
+        *d = c.to_digit(10).ok_or(BarcodeError::NotANumber)? as u8;
+    }
+    let expected = ean13_check_digit(&digits);
+    if digits[12] != expected {
+        return Err(BarcodeError::Checksum {
+            expected,
+            found: digits[12],
+        });
+    }
+    Ok(digits)
+}
+
+fn ean13_modules(digits: &[u8; 13]) -> String {
+    let parity = EAN13_PARITY[digits[0] as usize];
+    let mut modules = String::from("101");
+    for (d, p) in digits[1..7].iter().zip(parity.chars()) {
+        modules += match p {
+            'L' => EAN13_L[*d as usize],
+            _ => EAN13_G[*d as usize],
+        };
+    }
+    modules += "01010";
+    for d in &digits[7..13] {
+        modules += EAN13_R[*d as usize];
+    }
+    modules += "101";
+    modules
+}
+
+pub fn ean13_png(code: &str) -> Result<Vec<u8>, BarcodeError> {
+    let quiet = "0".repeat(EAN13_QUIET_MODULES);
+    let modules = format!("{quiet}{}{quiet}", ean13_modules(&parse_ean13(code)?));
+    let width = modules.len() * EAN13_MODULE_PX;
+    let row = modules
+        .chars()
+        .flat_map(|m| std::iter::repeat_n(if m == '1' { 0x00 } else { 0xff }, EAN13_MODULE_PX))
+        .collect::<Vec<u8>>();
+    encode_png(width, EAN13_HEIGHT_PX, &row.repeat(EAN13_HEIGHT_PX))
+}
+
+pub fn qr_png(data: &str) -> Result<Vec<u8>, BarcodeError> {
+    let code = QrCode::new(data)?;
+    let colors = code.to_colors();
+    let size = (code.width() + 2 * QR_QUIET_MODULES) * QR_MODULE_PX;
+    let mut pixels = vec![0xffu8; size * size];
+    for (i, c) in colors.iter().enumerate() {
+        if *c != Color::Dark {
+            continue;
+        }
+        let x0 = (i % code.width() + QR_QUIET_MODULES) * QR_MODULE_PX;
+        let y0 = (i / code.width() + QR_QUIET_MODULES) * QR_MODULE_PX;
+        for y in y0..y0 + QR_MODULE_PX {
+            pixels[y * size + x0..y * size + x0 + QR_MODULE_PX].fill(0x00);
+        }
+    }
+    encode_png(size, size, &pixels)
+}
+
+fn encode_png(width: usize, height: usize, pixels: &[u8]) -> Result<Vec<u8>, BarcodeError> {
+    let mut out = Vec::new();
+    let mut encoder = Encoder::new(&mut out, width as u32, height as u32);
+    encoder.set_color(ColorType::Grayscale);
+    encoder.set_depth(BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(pixels)?;
+    writer.finish()?;
+    Ok(out)
+}
+
+#[derive(Debug)]
+pub enum BarcodeError {
+    Length(usize),
+    NotANumber,
+    Checksum { expected: u8, found: u8 },
+    Encoding(String),
+}
+
+impl Localize for BarcodeError {
+    fn localize(&self, lang: Lang) -> String {
+        let texts = lang.texts();
+        match self {
+            BarcodeError::Length(n) => fill(texts.ean_length, &[n]),
+            BarcodeError::NotANumber => texts.ean_digits.to_string(),
+            BarcodeError::Checksum { expected, found } => {
+                fill(texts.ean_check_digit, &[found, expected])
+            }
+            BarcodeError::Encoding(e) => fill(texts.image_encoding, &[e]),
+        }
+    }
+}
+
+impl std::fmt::Display for BarcodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.localize(Lang::En))
+    }
+}
+
+impl std::error::Error for BarcodeError {}
+
+impl From<png::EncodingError> for BarcodeError {
+    fn from(e: png::EncodingError) -> Self {
+        BarcodeError::Encoding(format!("{:?}", e))
+    }
+}
+
+impl From<qrcode::types::QrError> for BarcodeError {
+    fn from(e: qrcode::types::QrError) -> Self {
+        BarcodeError::Encoding(format!("{:?}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_ean13_codes() {
+        assert_eq!(ean13_check_digit(&[5, 9, 0, 1, 2, 3, 4, 1, 2, 3, 4, 5]), 7);
+        assert_eq!(ean13_check_digit(&[4, 0, 0, 6, 3, 8, 1, 3, 3, 3, 9, 3]), 1);
+        let digits = parse_ean13(" 5901234123457 ").unwrap();
+        assert_eq!(ean13_modules(&digits).len(), 95);
+        assert!(ean13_png("5901234123457").unwrap().starts_with(b"\x89PNG"));
+
+        assert!(matches!(
+            parse_ean13("590123412345"),
+            Err(BarcodeError::Length(12))
+        ));
+        assert!(matches!(
+            parse_ean13("59012341234x7"),
+            Err(BarcodeError::NotANumber)
+        ));
+        assert!(matches!(
+            parse_ean13("5901234123458"),
+            Err(BarcodeError::Checksum {
+                expected: 7,
+                found: 8
+            })
+        ));
+    }
+}
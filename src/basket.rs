@@ -0,0 +1,308 @@
+use std::{collections::BTreeMap, fmt::Display, str::FromStr};
+
+use crate::{
+    api::Offer,
+    cache::OfferSnapshot,
+    filter::parse_price,
+    i18n::{fill, Lang, Localize},
+};
+
+const MAX_QUANTITY: u32 = 999;
+
+/// A product to buy, e.g. `mleko x3`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasketItem {
+    pub query: String,
+    pub quantity: u32,
+}
+
+impl FromStr for BasketItem {
+    type Err = BasketError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (query, quantity) = match s.rsplit_once(' ') {
+            Some((query, n)) if n.starts_with('x') && n.len() > 1 => (
+                query.trim(),
+                n[1..]
+                    .parse()
+                    .map_err(|_| BasketError::InvalidQuantity(n.to_string()))?,
+            ),
+            _ => (s, 1),
+        };
+        if query.is_empty() || quantity == 0 {
+            return Err(BasketError::InvalidItem(s.to_string()));
+        }
+        if quantity > MAX_QUANTITY {
+            return Err(BasketError::TooMany(query.to_string()));
+        }
+        Ok(BasketItem {
+            query: query.to_string(),
+            quantity,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum BasketError {
+    InvalidQuantity(String),
+    InvalidItem(String),
+    /// More than [`MAX_QUANTITY`] of the product.
+    TooMany(String),
+}
+
+impl Localize for BasketError {
+    fn localize(&self, lang: Lang) -> String {
+        let texts = lang.texts();
+        match self {
+            BasketError::InvalidQuantity(n) => fill(texts.invalid_quantity, &[n]),
+            BasketError::InvalidItem(item) => fill(texts.invalid_item, &[item]),
+            BasketError::TooMany(query) => fill(texts.too_many_items, &[&MAX_QUANTITY, query]),
+        }
+    }
+}
+
+impl Display for BasketError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.localize(Lang::En))
+    }
+}
+
+/// How many items can be bought at the offer price, e.g. `"max 6 szt."`.
+fn parse_limit(limit: &str) -> Option<u32> {
+    let digits = limit
+        .chars()
+        .skip_while(|c| !c.is_ascii_digit())
+        .take_while(|c| c.is_ascii_digit())
+        .collect::<String>();
+    digits.parse().ok()
+}
+
+fn offer_price(offer: &Offer, regular: Option<u32>) -> Option<u32> {
+    offer.price().or_else(|| {
+        Some(regular?.checked_mul((100 - offer.discount_percent.clamp(0, 100)) as u32)? / 100)
+    })
+}
+
+#[derive(Debug, Default)]
+pub struct CardPlan {
+    /// Product, quantity and cost.
+    pub items: Vec<(String, u32, u32)>,
+    pub total: u32,
+    pub regular: u32,
+}
+
+/// Cheapest way to buy a basket with the offers of all accounts.
+#[derive(Debug, Default)]
+pub struct BasketPlan {
+    pub cards: BTreeMap<String, CardPlan>,
+    /// Bought at the regular price, as the offer limits ran out.
+    pub regular: CardPlan,
+    /// Items without any offer.
+    pub missing: Vec<String>,
+    /// Best account when buying everything with one card and its total.
+    pub single: Option<(String, u32)>,
+}
+
+struct Candidate<'a> {
+    account: &'a str,
+    price: u32,
+    limit: Option<u32>,
+}
+
+impl BasketPlan {
+    pub fn new(items: &[BasketItem], snapshot: &OfferSnapshot) -> Self {
+        let mut plan = BasketPlan::default();
+        let mut singles: BTreeMap<&str, u32> = snapshot
+            .offers
+            .keys()
+            .map(|account| (account.as_str(), 0))
+            .collect();
+        for item in items {
+            let hits = snapshot.search(&item.query);
+            let Some((_, best)) = hits.first() else {
+                plan.missing.push(item.query.clone());
+                continue;
+            };
+            let name = best.name.clone();
+            let regular = parse_price(&best.regular_price);
+            let mut candidates: Vec<Candidate> = Vec::new();
+            for (account, offer) in hits.iter().filter(|(_, o)| o.name == name) {
+                let Some(price) = offer_price(offer, regular) else {
+                    continue;
+                };
+                match candidates.iter_mut().find(|c| c.account == *account) {
+                    Some(c) if c.price <= price => {}
+                    Some(c) => {
+                        c.price = price;
+                        c.limit = parse_limit(&offer.limit);
+                    }
+                    None => candidates.push(Candidate {
+                        account,
+                        price,
+                        limit: parse_limit(&offer.limit),
+                    }),
+                }
+            }
+            if candidates.is_empty() {
+                plan.missing.push(item.query.clone());
+                continue;
+            }
+            candidates.sort_by(|a, b| (a.price, a.account).cmp(&(b.price, b.account)));
+            let regular = regular.unwrap_or(candidates[candidates.len() - 1].price);
+
+            let mut remaining = item.quantity;
+            for c in &candidates {
+                if remaining == 0 {
+                    break;
+                }
+                let quantity = remaining.min(c.limit.unwrap_or(u32::MAX));
+                remaining -= quantity;
+                plan.cards
+                    .entry(c.account.to_string())
+                    .or_default()
+                    .add(&name, quantity, c.price, regular);
+            }
+            if remaining > 0 {
+                plan.regular.add(&name, remaining, regular, regular);
+            }
+
+            for (account, total) in singles.iter_mut() {
+                let single = match candidates.iter().find(|c| c.account == *account) {
+                    Some(c) => {
+                        let quantity = item.quantity.min(c.limit.unwrap_or(u32::MAX));
+                        cost(quantity, c.price)
+                            .saturating_add(cost(item.quantity - quantity, regular))
+                    }
+                    None => cost(item.quantity, regular),
+                };
+                *total = total.saturating_add(single);
+            }
+        }
+        plan.single = singles
+            .into_iter()
+            .min_by_key(|(account, total)| (*total, *account))
+            .map(|(account, total)| (account.to_string(), total));
+        plan
+    }
+
+    pub fn total(&self) -> u32 {
+        self.cards
+            .values()
+            .fold(self.regular.total, |sum, c| sum.saturating_add(c.total))
+    }
+
+    pub fn savings(&self) -> u32 {
+        self.cards.values().fold(0, |sum, c| {
+            sum.saturating_add(c.regular.saturating_sub(c.total))
+        })
+    }
+}
+
+// saturates instead of overflowing on absurd prices from the api
+fn cost(quantity: u32, price: u32) -> u32 {
+    quantity.saturating_mul(price)
+}
+
+impl CardPlan {
+    fn add(&mut self, name: &str, quantity: u32, price: u32, regular: u32) {
+        self.items
+            .push((name.to_string(), quantity, cost(quantity, price)));
+        self.total = self.total.saturating_add(cost(quantity, price));
+        self.regular = self.regular.saturating_add(cost(quantity, regular));
+    }
+
+    fn render_items(&self, lines: &mut Vec<String>, lang: Lang) {
+        for (name, quantity, cost) in &self.items {
+            lines.push(format!("    {name} x{quantity} - {}", lang.price(*cost)));
+        }
+    }
+}
+
+impl BasketPlan {
+    pub fn render(&self, lang: Lang) -> String {
+        let texts = lang.texts();
+        let mut lines = Vec::new();
+        if self.cards.is_empty() {
+            lines.push(texts.no_basket_offers.to_string());
+        }
+        for (account, card) in &self.cards {
+            lines.push(fill(
+                texts.basket_card,
+                &[
+                    account,
+                    &lang.price(card.total),
+                    &lang.price(card.regular.saturating_sub(card.total)),
+                ],
+            ));
+            card.render_items(&mut lines, lang);
+        }
+        if !self.regular.items.is_empty() {
+            lines.push(fill(texts.over_limits, &[&lang.price(self.regular.total)]));
+            self.regular.render_items(&mut lines, lang);
+        }
+        if !self.cards.is_empty() {
+            lines.push(fill(
+                texts.basket_total,
+                &[&lang.price(self.total()), &lang.price(self.savings())],
+            ));
+        }
+        if let Some((account, total)) = &self.single {
+            if self.cards.len() > 1 && *total > self.total() {
+                lines.push(fill(
+                    texts.single_card,
+                    &[
+                        account,
+                        &lang.price(*total),
+                        &lang.price(total - self.total()),
+                    ],
+                ));
+            }
+        }
+        if !self.missing.is_empty() {
+            lines.push(fill(texts.missing_offers, &[&self.missing.join(", ")]));
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake::synced_cache;
+
+    #[tokio::test]
+    async fn splits_the_basket_over_offer_limits() {
+        let cache = synced_cache(&["anna", "jan"]).await;
+        let items = ["mleko x8", "kawa", "chleb"]
+            .iter()
+            .map(|i| i.parse().unwrap())
+            .collect::<Vec<BasketItem>>();
+        let plan = BasketPlan::new(&items, &cache.snapshot());
+
+        // the milk is limited to 6 per card
+        assert_eq!(plan.cards["anna"].total, 6 * 249 + 3999);
+        assert_eq!(
+            plan.cards["jan"].items,
+            [("Mleko UHT 3,2%".to_string(), 2, 498)]
+        );
+        assert_eq!(plan.savings(), 8 * (359 - 249) + (5999 - 3999));
+        assert_eq!(
+            plan.single,
+            Some(("anna".to_string(), 6 * 249 + 2 * 359 + 3999))
+        );
+        assert_eq!(plan.missing, ["chleb"]);
+
+        assert!("mleko x20000000".parse::<BasketItem>().is_err());
+        assert_eq!(
+            "mleko x999".parse::<BasketItem>().map(|i| i.quantity),
+            Ok(999)
+        );
+        let too_many = "mleko x1000".parse::<BasketItem>().unwrap_err();
+        assert_eq!(too_many.to_string(), "at most x999 of \"mleko\"");
+        assert_eq!(
+            too_many.localize(Lang::Pl),
+            "najwyżej x999 produktu \"mleko\""
+        );
+    }
+}
@@ -1,45 +1,375 @@
-use std::collections::HashMap;
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    fmt::Display,
+    sync::{Arc, RwLock},
+};
 
-use chrono::{Datelike, Utc};
+use chrono::{DateTime, Duration, Utc};
+use chrono_tz::Europe::Warsaw;
+use futures::{stream, StreamExt};
+use tokio::sync::Mutex;
 
 use crate::{
-    api::{ApiError, BiedApi, Offer},
-    db::BiedStore,
+    api::{ApiError, AuthenticatedUser, Offer, OfferSource},
+    cookies::CookieJar,
+    i18n::{fill, Lang, Localize},
+    search::OfferIndex,
+    secrets::CacheConfig,
 };
 
 // TODO: move cashe to file
 pub struct BiedCache {
+    snapshot: RwLock<Arc<OfferSnapshot>>,
+    // held for the whole sync so only one runs at a time
+    syncing: Mutex<()>,
+    ttl: Duration,
+    parallelism: usize,
+}
+
+/// Immutable view of the offers, replaced as a whole after every sync.
+#[derive(Default)]
+pub struct OfferSnapshot {
     pub offers: HashMap<String, Vec<Offer>>,
-    collect_day: u32,
+    pub synced_at: HashMap<String, DateTime<Utc>>,
+    /// Api profile of every account, offers are grouped by its brand.
+    pub profiles: HashMap<String, String>,
+    index: OfferIndex,
+}
+
+#[derive(Default, Debug, PartialEq, Eq)]
+pub struct SyncRequest {
+    /// Refresh accounts even if their offers are still fresh.
+    pub force: bool,
+    /// Only refresh the account with this title.
+    pub only: Option<String>,
+}
+
+impl SyncRequest {
+    /// Parses `/sync [force] [title]`, titles may contain spaces. Naming an
+    /// account forces it, as that's what asking for it by name means.
+    pub fn parse(args: &str) -> Self {
+        let args = args.trim();
+        let (force, title) = match args.strip_prefix("force") {
+            Some(rest) if rest.is_empty() || rest.starts_with(char::is_whitespace) => {
+                (true, rest.trim())
+            }
+            _ => (false, args),
+        };
+        if title.is_empty() {
+            Self { force, only: None }
+        } else {
+            Self {
+                force: true,
+                only: Some(title.to_string()),
+            }
+        }
+    }
+}
+
+/// Offers are fresh until the TTL passes or the day changes in Poland,
+/// as that's when the new offers show up in the app.
+pub fn is_fresh(synced_at: DateTime<Utc>, now: DateTime<Utc>, ttl: Duration) -> bool {
+    now - synced_at < ttl
+        && synced_at.with_timezone(&Warsaw).date_naive() == now.with_timezone(&Warsaw).date_naive()
 }
 
 impl BiedCache {
-    pub fn new() -> Self {
+    pub fn new(config: &CacheConfig) -> Self {
         Self {
-            offers: HashMap::new(),
-            collect_day: u32::MAX,
+            snapshot: RwLock::new(Arc::new(OfferSnapshot::default())),
+            syncing: Mutex::new(()),
+            ttl: Duration::minutes(config.ttl_minutes),
+            parallelism: config.sync_parallelism.max(1),
         }
     }
 
-    // TODO: auto sync every day
+    pub fn snapshot(&self) -> Arc<OfferSnapshot> {
+        self.snapshot.read().unwrap().clone()
+    }
+
     pub async fn sync_offers(
-        &mut self,
-        store: &mut BiedStore,
-        api: &BiedApi,
-    ) -> Result<(), ApiError> {
-        if Utc::now().day() == self.collect_day {
-            return Ok(());
+        &self,
+        accounts: Vec<(String, AuthenticatedUser)>,
+        api: &dyn OfferSource,
+        request: SyncRequest,
+    ) -> SyncReport {
+        let _syncing = self.syncing.lock().await;
+        let old = self.snapshot();
+        let now = Utc::now();
+        let mut report = SyncReport::default();
+        let mut offers = HashMap::new();
+        let mut synced_at = HashMap::new();
+        let profiles = accounts
+            .iter()
+            .map(|(name, user)| (name.clone(), user.profile.clone()))
+            .collect();
+
+        let mut outdated = Vec::new();
+        for (name, user) in accounts {
+            let requested = request.only.as_ref().is_none_or(|only| *only == name);
+            let fresh = old
+                .synced_at
+                .get(&name)
+                .is_some_and(|t| is_fresh(*t, now, self.ttl));
+            match old.offers.get(&name) {
+                Some(of) if !requested || (fresh && !request.force) => {
+                    if requested {
+                        report
+                            .accounts
+                            .push((name.clone(), AccountSync::Fresh(of.len())));
+                    }
+                    offers.insert(name.clone(), of.clone());
+                    synced_at.extend(old.synced_at.get(&name).map(|t| (name, *t)));
+                }
+                _ if requested => outdated.push((name, user)),
+                _ => {}
+            }
         }
-        self.offers.clear();
-        for (name, user) in store.fetch_accounts() {
-            for of in api.get_offers(user.auth).await {
-                self.offers.insert(name.clone(), of);
+
+        let results = stream::iter(outdated)
+            .map(|(name, mut user)| async move {
+                let result = api.get_offers(&user.profile, &mut user.auth).await;
+                (name, user.auth.cookies, result)
+            })
+            .buffer_unordered(self.parallelism)
+            .collect::<Vec<_>>()
+            .await;
+        for (name, cookies, result) in results {
+            report.cookies.push((name.clone(), cookies));
+            match result {
+                Ok(of) => {
+                    report
+                        .accounts
+                        .push((name.clone(), AccountSync::Synced(of.len())));
+                    synced_at.insert(name.clone(), Utc::now());
+                    offers.insert(name, of);
+                }
+                // keep the last known offers of accounts that failed to sync
+                Err(e) => {
+                    if let Some(of) = old.offers.get(&name) {
+                        offers.insert(name.clone(), of.clone());
+                        synced_at.extend(old.synced_at.get(&name).map(|t| (name.clone(), *t)));
+                    }
+                    report.accounts.push((name, AccountSync::Failed(e)));
+                }
             }
         }
-        Ok(())
+        report.accounts.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let index = OfferIndex::build(&offers);
+        *self.snapshot.write().unwrap() = Arc::new(OfferSnapshot {
+            offers,
+            synced_at,
+            profiles,
+            index,
+        });
+        report
+    }
+}
+
+impl OfferSnapshot {
+    /// Offers matching the query along with their account,
+    /// ranked by relevance and then by discount.
+    pub fn search(&self, query: &str) -> Vec<(&str, &Offer)> {
+        let mut hits = self
+            .index
+            .search(query)
+            .into_iter()
+            .filter_map(|hit| {
+                let offer = self
+                    .offers
+                    .get(hit.account)?
+                    .iter()
+                    .find(|o| o.id() == hit.id)?;
+                Some((hit.score, (hit.account, offer)))
+            })
+            .collect::<Vec<_>>();
+        hits.sort_by_key(|(score, (_, o))| Reverse((*score, o.discount_percent)));
+        hits.into_iter().map(|(_, hit)| hit).collect()
     }
 
-    pub async fn get_offers(&mut self, title: &str) -> Option<&Vec<Offer>> {
+    pub fn get_offers(&self, title: &str) -> Option<&Vec<Offer>> {
         self.offers.get(title)
     }
 }
+
+pub enum AccountSync {
+    Synced(usize),
+    Fresh(usize),
+    Failed(ApiError),
+}
+
+#[derive(Default)]
+pub struct SyncReport {
+    pub accounts: Vec<(String, AccountSync)>,
+    /// Cookie jars of the fetched accounts, to be saved back to the store.
+    pub cookies: Vec<(String, CookieJar)>,
+}
+
+impl SyncReport {
+    pub fn render(&self, lang: Lang) -> String {
+        let texts = lang.texts();
+        if self.accounts.is_empty() {
+            return texts.no_accounts_to_sync.to_string();
+        }
+        let count =
+            |f: fn(&AccountSync) -> bool| self.accounts.iter().filter(|(_, r)| f(r)).count();
+        let synced = count(|r| matches!(r, AccountSync::Synced(_)));
+        let cached = count(|r| matches!(r, AccountSync::Fresh(_)));
+        let fetched = self.accounts.len() - cached;
+        let mut lines = vec![if cached == 0 {
+            fill(texts.synced_accounts, &[&synced, &fetched])
+        } else {
+            fill(texts.synced_cached_accounts, &[&synced, &fetched, &cached])
+        }];
+        for (name, result) in &self.accounts {
+            lines.push(match result {
+                AccountSync::Synced(count) => fill(texts.account_synced, &[name, count]),
+                AccountSync::Fresh(count) => fill(texts.account_fresh, &[name, count]),
+                AccountSync::Failed(e) => format!("{name}: {}", e.localize(lang)),
+            });
+        }
+        lines.join("\n")
+    }
+}
+
+impl Display for SyncReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.render(Lang::En))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake::{account, FakeResponse, FakeSource, OFFERS_FIXTURE};
+
+    fn cache() -> BiedCache {
+        BiedCache::new(&CacheConfig::default())
+    }
+
+    fn accounts() -> Vec<(String, AuthenticatedUser)> {
+        vec![
+            ("anna".to_string(), account("a")),
+            ("jan".to_string(), account("j")),
+        ]
+    }
+
+    #[tokio::test]
+    async fn syncs_all_accounts() {
+        let api = FakeSource::default()
+            .with("a", FakeResponse::Offers(OFFERS_FIXTURE.to_string()))
+            .with("j", FakeResponse::AuthExpired);
+        let cache = cache();
+
+        let report = cache
+            .sync_offers(accounts(), &api, SyncRequest::default())
+            .await;
+
+        assert!(matches!(report.accounts[0], (ref n, AccountSync::Synced(2)) if n == "anna"));
+        assert!(
+            matches!(report.accounts[1], (ref n, AccountSync::Failed(ApiError::AuthExpired)) if n == "jan")
+        );
+        let snapshot = cache.snapshot();
+        assert_eq!(snapshot.get_offers("anna").unwrap().len(), 2);
+        assert!(snapshot.get_offers("jan").is_none());
+        assert_eq!(snapshot.search("kawa")[0].1.name, "Kawa ziarnista");
+    }
+
+    #[tokio::test]
+    async fn reuses_fresh_offers_unless_forced() {
+        let api = FakeSource::default()
+            .with("a", FakeResponse::Offers(OFFERS_FIXTURE.to_string()))
+            .with("j", FakeResponse::Offers(OFFERS_FIXTURE.to_string()));
+        let cache = cache();
+
+        cache
+            .sync_offers(accounts(), &api, SyncRequest::default())
+            .await;
+        let report = cache
+            .sync_offers(accounts(), &api, SyncRequest::default())
+            .await;
+        assert_eq!(api.calls(), 2);
+        assert!(matches!(report.accounts[0].1, AccountSync::Fresh(2)));
+        assert!(report
+            .to_string()
+            .starts_with("Synced 0 of 0 accounts, 2 up to date in the cache:"));
+
+        cache
+            .sync_offers(
+                accounts(),
+                &api,
+                SyncRequest {
+                    force: true,
+                    only: Some("jan".to_string()),
+                },
+            )
+            .await;
+        assert_eq!(api.calls(), 3);
+    }
+
+    #[tokio::test]
+    async fn keeps_old_offers_when_sync_fails() {
+        let cache = cache();
+        let ok = FakeSource::default().with("a", FakeResponse::Offers(OFFERS_FIXTURE.to_string()));
+        cache
+            .sync_offers(accounts(), &ok, SyncRequest::default())
+            .await;
+
+        let failing = FakeSource::default().with("a", FakeResponse::Network);
+        let report = cache
+            .sync_offers(
+                accounts(),
+                &failing,
+                SyncRequest {
+                    force: true,
+                    only: None,
+                },
+            )
+            .await;
+
+        assert!(matches!(
+            report.accounts[0].1,
+            AccountSync::Failed(ApiError::Network(_))
+        ));
+        assert_eq!(cache.snapshot().get_offers("anna").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn offers_expire_at_warsaw_midnight() {
+        let ttl = Duration::hours(6);
+        // 22:30 UTC is already past midnight in Warsaw
+        let synced = DateTime::parse_from_rfc3339("2022-10-18T21:30:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let now = DateTime::parse_from_rfc3339("2022-10-18T22:30:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert!(!is_fresh(synced, now, ttl));
+        assert!(is_fresh(synced, synced + Duration::minutes(10), ttl));
+    }
+
+    #[test]
+    fn parses_sync_arguments() {
+        let only = |title: &str| SyncRequest {
+            force: true,
+            only: Some(title.to_string()),
+        };
+        assert_eq!(SyncRequest::parse(" "), SyncRequest::default());
+        assert_eq!(
+            SyncRequest::parse("force"),
+            SyncRequest {
+                force: true,
+                only: None
+            }
+        );
+        assert_eq!(SyncRequest::parse("Jan Kowalski"), only("Jan Kowalski"));
+        assert_eq!(
+            SyncRequest::parse("force  Jan Kowalski "),
+            only("Jan Kowalski")
+        );
+        assert_eq!(SyncRequest::parse("force force"), only("force"));
+        assert_eq!(SyncRequest::parse("forced"), only("forced"));
+    }
+}
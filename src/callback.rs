@@ -0,0 +1,143 @@
+use crate::{filter::OfferFilter, preferences::Setting};
+
+/// Bumped whenever the encoding changes, buttons of other versions are outdated.
+const VERSION: &str = "1";
+
+/// What an inline button does, encoded into its callback data as
+/// `1|<kind>|<fields>` to fit in the 64 bytes telegram allows. Accounts are
+/// encoded as a hash of their title, so `decode` yields the hashes in place
+/// of the titles until `resolve_accounts` swaps them back. Offers are keyed by
+/// their id, as their positions change with every sync.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Callback {
+    ShowAccount(String),
+    Offers(OfferFilter),
+    /// Account and offer id.
+    Activate(String, String),
+    ActivateAll(String),
+    /// Account and offer id.
+    Details(String, String),
+    Setting(Setting),
+}
+
+/// Telegram rejects buttons with longer callback data.
+const MAX_DATA_LEN: usize = 64;
+
+/// Short stable key of an account title, FNV-1a.
+pub fn account_key(title: &str) -> String {
+    let hash = title.bytes().fold(0x811c9dc5u32, |h, b| {
+        (h ^ b as u32).wrapping_mul(0x01000193)
+    });
+    format!("{hash:08x}")
+}
+
+impl Callback {
+    /// `None` if the data doesn't fit in a button. Only offer ids are unbounded,
+    /// the longest filter takes 59 bytes.
+    pub fn encode(&self) -> Option<String> {
+        let fields = match self {
+            Callback::ShowAccount(account) => format!("a|{}", account_key(account)),
+            Callback::Offers(filter) => format!("f|{filter}"),
+            Callback::Activate(account, id) => format!("x|{}|{id}", account_key(account)),
+            Callback::ActivateAll(account) => format!("X|{}", account_key(account)),
+            Callback::Details(account, id) => format!("d|{}|{id}", account_key(account)),
+            Callback::Setting(setting) => format!("s|{setting}"),
+        };
+        let data = format!("{VERSION}|{fields}");
+        (data.len() <= MAX_DATA_LEN).then_some(data)
+    }
+
+    /// `None` for buttons of older versions or unknown kinds.
+    pub fn decode(data: &str) -> Option<Self> {
+        let mut fields = data.splitn(3, '|');
+        if fields.next()? != VERSION {
+            return None;
+        }
+        let kind = fields.next()?;
+        let rest = fields.next()?;
+        let pair = || rest.split_once('|');
+        Some(match kind {
+            "a" => Callback::ShowAccount(rest.to_string()),
+            "f" => Callback::Offers(rest.parse().ok()?),
+            "x" => {
+                let (key, id) = pair()?;
+                Callback::Activate(key.to_string(), id.to_string())
+            }
+            "X" => Callback::ActivateAll(rest.to_string()),
+            "d" => {
+                let (key, id) = pair()?;
+                Callback::Details(key.to_string(), id.to_string())
+            }
+            "s" => Callback::Setting(rest.parse().ok()?),
+            _ => return None,
+        })
+    }
+
+    /// Whether the callback names an account, which `resolve_accounts` needs the titles for.
+    pub fn has_account(&self) -> bool {
+        !matches!(self, Callback::Offers(_) | Callback::Setting(_))
+    }
+
+    /// Replaces the account keys of a decoded callback with the titles,
+    /// `None` if the account is gone.
+    pub fn resolve_accounts(self, titles: &[String]) -> Option<Self> {
+        let title = |key: String| titles.iter().find(|t| account_key(t) == key).cloned();
+        Some(match self {
+            Callback::ShowAccount(key) => Callback::ShowAccount(title(key)?),
+            Callback::Activate(key, id) => Callback::Activate(title(key)?, id),
+            Callback::ActivateAll(key) => Callback::ActivateAll(title(key)?),
+            Callback::Details(key, id) => Callback::Details(title(key)?, id),
+            other @ (Callback::Offers(_) | Callback::Setting(_)) => other,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_callback_data() {
+        let titles =
+            ["a title much longer than telegram would ever allow in callback data".to_string()];
+        let callbacks = [
+            Callback::ShowAccount(titles[0].clone()),
+            Callback::Offers("sort:price min:30% max:10zł cat:dairy".parse().unwrap()),
+            Callback::Offers(
+                "sort:discount min:100% max:42949672,95zł cat:household"
+                    .parse()
+                    .unwrap(),
+            ),
+            Callback::Activate(titles[0].clone(), "1001".to_string()),
+            Callback::ActivateAll(titles[0].clone()),
+            Callback::Details(titles[0].clone(), "1001".to_string()),
+            Callback::Setting(Setting::QuietHours),
+        ];
+        for callback in callbacks {
+            let data = callback.encode().unwrap();
+            assert!(data.len() <= MAX_DATA_LEN, "{data} is too long");
+            let decoded = Callback::decode(&data).unwrap();
+            if !decoded.has_account() {
+                assert_eq!(
+                    decoded.clone().resolve_accounts(&[]),
+                    Some(callback.clone())
+                );
+            }
+            assert_eq!(decoded.resolve_accounts(&titles), Some(callback));
+        }
+
+        // buttons sent before the protocol existed, or of a removed account
+        assert_eq!(Callback::decode("activate 1001 anna"), None);
+        assert_eq!(Callback::decode("anna"), None);
+        let removed = Callback::decode(&Callback::ActivateAll("jan".to_string()).encode().unwrap());
+        assert_eq!(removed.unwrap().resolve_accounts(&titles), None);
+        // an older protocol keyed offers by position
+        assert_eq!(Callback::decode("1|o|0a1b2c3d|12"), None);
+
+        let long_id = "9".repeat(MAX_DATA_LEN);
+        assert_eq!(
+            Callback::Details("anna".to_string(), long_id).encode(),
+            None
+        );
+    }
+}
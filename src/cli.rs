@@ -0,0 +1,72 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+
+use crate::secrets::DEFAULT_PROFILE;
+
+#[derive(Parser)]
+#[command(version, about = "A telegram bot sending loyalty app offers")]
+pub struct Cli {
+    /// Path of the config file, see `secrets.example.toml`.
+    #[arg(long, env = "BIEDBOT_CONFIG", default_value = "secrets.toml")]
+    pub config: PathBuf,
+    /// Directory of the account database.
+    #[arg(long, env = "BIEDBOT_DB_PATH", default_value = "biedstore")]
+    pub db_path: PathBuf,
+    /// One of off, error, warn, info, debug or trace.
+    #[arg(long, env = "BIEDBOT_LOG_LEVEL", default_value = "info")]
+    pub log_level: log::LevelFilter,
+    #[command(subcommand)]
+    pub command: Option<CliCommand>,
+}
+
+#[derive(Subcommand)]
+pub enum CliCommand {
+    /// Run the bot (the default).
+    Run,
+    /// Validate the config file and environment overrides.
+    CheckConfig,
+    /// Manage the stored accounts.
+    #[command(subcommand)]
+    Accounts(AccountsCommand),
+    /// Fetch the offers of every account and save the cookies the api rotated.
+    /// The offers themselves aren't stored, a running bot keeps its own.
+    Sync {
+        /// Print the fetched offers instead of queueing the alerts about them.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Inspect the offers of an account.
+    #[command(subcommand)]
+    Offers(OffersCommand),
+}
+
+#[derive(Subcommand)]
+pub enum AccountsCommand {
+    /// List all added accounts.
+    List,
+    /// Add an account.
+    Add {
+        title: String,
+        card_number: String,
+        phone_number: String,
+        users1: String,
+        users2: String,
+        csrf_token: String,
+        /// Api profile of the card, see `api_profiles` in the config.
+        #[arg(long, default_value = DEFAULT_PROFILE)]
+        profile: String,
+    },
+    /// Remove the account with the specified title.
+    Remove { title: String },
+    /// Rename an account.
+    Rename { old: String, new: String },
+    /// Set the api profile of an account.
+    Profile { title: String, profile: String },
+}
+
+#[derive(Subcommand)]
+pub enum OffersCommand {
+    /// Fetch and print the offers of an account.
+    Show { title: String },
+}
@@ -0,0 +1,116 @@
+use std::collections::BTreeMap;
+
+use chrono::Utc;
+use cookie::Cookie;
+use reqwest::header::{HeaderMap, SET_COOKIE};
+use serde::{Deserialize, Serialize};
+
+/// Cookies the api set for an account, kept between requests so rotated session cookies aren't lost.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct CookieJar {
+    cookies: BTreeMap<String, StoredCookie>,
+    /// Cookies set or removed since the jar was loaded, `None` for removed.
+    #[serde(skip)]
+    changes: BTreeMap<String, Option<StoredCookie>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct StoredCookie {
+    value: String,
+    /// Unix timestamp, session cookies never expire.
+    expires: Option<i64>,
+}
+
+impl CookieJar {
+    /// Applies the `Set-Cookie` headers of a response.
+    pub fn update(&mut self, headers: &HeaderMap) {
+        let now = Utc::now().timestamp();
+        for header in headers.get_all(SET_COOKIE) {
+            let Some(cookie) = header.to_str().ok().and_then(|h| Cookie::parse(h).ok()) else {
+                log::warn!("ignoring malformed cookie {header:?}");
+                continue;
+            };
+            let expires = match (cookie.max_age(), cookie.expires_datetime()) {
+                (Some(max_age), _) => Some(now + max_age.whole_seconds()),
+                (None, Some(expires)) => Some(expires.unix_timestamp()),
+                (None, None) => None,
+            };
+            let change = expires.is_none_or(|e| e > now).then(|| StoredCookie {
+                value: cookie.value().to_string(),
+                expires,
+            });
+            self.apply(cookie.name(), change.clone());
+            self.changes.insert(cookie.name().to_string(), change);
+        }
+    }
+
+    fn apply(&mut self, name: &str, change: Option<StoredCookie>) {
+        match change {
+            Some(cookie) => self.cookies.insert(name.to_string(), cookie),
+            None => self.cookies.remove(name),
+        };
+    }
+
+    /// Applies the cookies `other` got since it was loaded, keeping the rest,
+    /// so a jar loaded earlier doesn't undo cookies rotated in the meantime.
+    pub fn merge(&mut self, other: &CookieJar) {
+        for (name, change) in &other.changes {
+            self.apply(name, change.clone());
+        }
+    }
+
+    /// Cookies that haven't expired yet.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        let now = Utc::now().timestamp();
+        self.cookies
+            .iter()
+            .filter(move |(_, c)| c.expires.is_none_or(|e| e > now))
+            .map(|(name, c)| (name.as_str(), c.value.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::HeaderValue;
+
+    use super::*;
+
+    fn set_cookies(cookies: &[&'static str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for c in cookies {
+            headers.append(SET_COOKIE, HeaderValue::from_static(c));
+        }
+        headers
+    }
+
+    #[test]
+    fn keeps_rotated_and_drops_expired_cookies() {
+        let mut jar = CookieJar::default();
+        jar.update(&set_cookies(&[
+            "nr1Users=old; Path=/; HttpOnly",
+            "session=abc; Max-Age=3600",
+        ]));
+        jar.update(&set_cookies(&[
+            "nr1Users=new; Path=/",
+            "session=; Max-Age=0",
+            "gone=x; Expires=Thu, 01 Jan 1970 00:00:00 GMT",
+        ]));
+        assert_eq!(jar.iter().collect::<Vec<_>>(), vec![("nr1Users", "new")]);
+    }
+
+    #[test]
+    fn merges_only_the_changed_cookies() {
+        let mut stored = CookieJar::default();
+        stored.update(&set_cookies(&["nr1Users=old", "session=abc"]));
+        let mut earlier = stored.clone();
+        earlier.changes.clear();
+        stored.update(&set_cookies(&["nr1Users=new"]));
+        earlier.update(&set_cookies(&["session=; Max-Age=0", "other=x"]));
+
+        stored.merge(&earlier);
+        assert_eq!(
+            stored.iter().collect::<Vec<_>>(),
+            vec![("nr1Users", "new"), ("other", "x")]
+        );
+    }
+}
@@ -1,17 +1,84 @@
-use sled::Tree;
+use std::{collections::HashSet, path::Path};
 
-use crate::api::AuthenticatedUser;
+use chrono::{DateTime, TimeZone, Utc};
+use sled::{
+    transaction::{abort, ConflictableTransactionError, TransactionError},
+    Transactional, Tree,
+};
+
+use crate::{
+    api::{AccountSummary, AuthenticatedUser, Transaction},
+    cookies::CookieJar,
+    i18n::{fill, Lang, Localize},
+    notify::Outbox,
+    preferences::Preferences,
+    secrets::DEFAULT_PROFILE,
+    validation::{validate_account, validate_title, ValidationError},
+};
 
 pub struct BiedStore {
     accounts: Tree,
+    cookies: Tree,
+    profiles: Tree,
+    activations: Tree,
+    summaries: Tree,
+    transactions: Tree,
+    /// Shopping lists keyed by telegram user id.
+    shopping: Tree,
+    /// Preferences keyed by telegram user id.
+    preferences: Tree,
+    /// Notifications waiting for delivery keyed by telegram user id.
+    outbox: Tree,
 }
 
 impl BiedStore {
-    pub fn new(dir: &str) -> Self {
-        let db = sled::open(dir).expect("failed to open database");
-        Self {
-            accounts: db.open_tree("accounts").expect("failed to create db tree"),
-        }
+    /// Fails with [`StoreError::Locked`] while another process, like the bot, has it open.
+    pub fn new(dir: impl AsRef<Path>) -> Result<Self, StoreError> {
+        let dir = dir.as_ref();
+        let db = sled::open(dir).map_err(|e| match e {
+            // sled reports the lock as a generic io error
+            sled::Error::Io(io) if io.to_string().contains("could not acquire lock") => {
+                StoreError::Locked(dir.display().to_string())
+            }
+            e => e.into(),
+        })?;
+        Self::open(db)
+    }
+
+    #[cfg(test)]
+    pub fn temporary() -> Self {
+        Self::open(
+            sled::Config::new()
+                .temporary(true)
+                .open()
+                .expect("failed to open database"),
+        )
+        .expect("failed to open database")
+    }
+
+    fn open(db: sled::Db) -> Result<Self, StoreError> {
+        Ok(Self {
+            accounts: db.open_tree("accounts")?,
+            cookies: db.open_tree("cookies")?,
+            profiles: db.open_tree("profiles")?,
+            activations: db.open_tree("activations")?,
+            summaries: db.open_tree("summaries")?,
+            transactions: db.open_tree("transactions")?,
+            shopping: db.open_tree("shopping")?,
+            preferences: db.open_tree("preferences")?,
+            outbox: db.open_tree("outbox")?,
+        })
+    }
+
+    /// Trees keyed by account title besides `accounts`, moved along on rename.
+    fn account_data(&self) -> [&Tree; 5] {
+        [
+            &self.cookies,
+            &self.profiles,
+            &self.activations,
+            &self.summaries,
+            &self.transactions,
+        ]
     }
 
     pub fn insert_account(
@@ -19,8 +86,13 @@ impl BiedStore {
         title: &str,
         user: AuthenticatedUser,
     ) -> Result<(), StoreError> {
+        validate_title(title)?;
+        let user = validate_account(user)?;
         self.accounts
-            .insert(&title, bincode::serialize(&user).unwrap())?;
+            .insert(title, bincode::serialize(&user).unwrap())?;
+        self.profiles.insert(title, user.profile.as_str())?;
+        // cookies of the old session don't belong to the new tokens
+        self.cookies.remove(title)?;
         Ok(())
     }
 
@@ -29,50 +101,445 @@ impl BiedStore {
             .range::<&str, _>(..)
             .filter_map(|e| e.ok())
             .filter_map(|d| {
-                Some((
-                    String::from_utf8(d.0.to_vec()).ok()?,
-                    bincode::deserialize(&d.1).ok()?,
-                ))
+                let title = String::from_utf8(d.0.to_vec()).ok()?;
+                let user = bincode::deserialize(&d.1).ok()?;
+                Some((title.clone(), self.with_account_data(&title, user)))
             })
             .collect() // TODO: return iterator instead
     }
 
+    /// Titles of the accounts, without reading their data.
+    pub fn account_titles(&self) -> Vec<String> {
+        self.accounts
+            .iter()
+            .keys()
+            .filter_map(|key| String::from_utf8(key.ok()?.to_vec()).ok())
+            .collect()
+    }
+
     pub fn fetch_account(&self, title: &str) -> Result<AuthenticatedUser, StoreError> {
-        Ok(bincode::deserialize(&self.accounts.get(title)?.ok_or(
-            StoreError("no account with that name".to_string()),
-        )?)?)
+        let user = bincode::deserialize(&self.accounts.get(title)?.ok_or(StoreError::NoAccount)?)?;
+        Ok(self.with_account_data(title, user))
+    }
+
+    // a broken cookie jar only costs a new session, so it doesn't hide the account
+    fn with_account_data(&self, title: &str, mut user: AuthenticatedUser) -> AuthenticatedUser {
+        user.auth.cookies = self.fetch_cookies(title).unwrap_or_else(|e| {
+            log::warn!("couldn't load the cookies of {title}: {e}");
+            Default::default()
+        });
+        user.profile = self.fetch_profile(title).unwrap_or_else(|e| {
+            log::warn!("couldn't load the profile of {title}: {e}");
+            DEFAULT_PROFILE.to_string()
+        });
+        user
+    }
+
+    // accounts added before profiles existed belong to the default one
+    fn fetch_profile(&self, title: &str) -> Result<String, StoreError> {
+        Ok(match self.profiles.get(title)? {
+            Some(profile) => String::from_utf8_lossy(&profile).into_owned(),
+            None => DEFAULT_PROFILE.to_string(),
+        })
+    }
+
+    /// Ids of the offers activated on the account.
+    pub fn fetch_activated(&self, title: &str) -> Result<HashSet<String>, StoreError> {
+        Ok(match self.activations.get(title)? {
+            Some(ids) => bincode::deserialize(&ids)?,
+            None => HashSet::new(),
+        })
+    }
+
+    pub fn save_activated(&mut self, title: &str, ids: &HashSet<String>) -> Result<(), StoreError> {
+        self.activations
+            .insert(title, bincode::serialize(ids).unwrap())?;
+        Ok(())
+    }
+
+    /// Last fetched summary of the account and when it was fetched.
+    pub fn fetch_summary(
+        &self,
+        title: &str,
+    ) -> Result<Option<(DateTime<Utc>, AccountSummary)>, StoreError> {
+        let Some(data) = self.summaries.get(title)? else {
+            return Ok(None);
+        };
+        let (fetched_at, summary): (i64, AccountSummary) = bincode::deserialize(&data)?;
+        Ok(Utc
+            .timestamp_opt(fetched_at, 0)
+            .single()
+            .map(|t| (t, summary)))
+    }
+
+    pub fn save_summary(
+        &mut self,
+        title: &str,
+        summary: &AccountSummary,
+        fetched_at: DateTime<Utc>,
+    ) -> Result<(), StoreError> {
+        self.summaries.insert(
+            title,
+            bincode::serialize(&(fetched_at.timestamp(), summary)).unwrap(),
+        )?;
+        Ok(())
+    }
+
+    /// Purchase history of the account, oldest first.
+    pub fn fetch_transactions(&self, title: &str) -> Result<Vec<Transaction>, StoreError> {
+        Ok(match self.transactions.get(title)? {
+            Some(data) => bincode::deserialize(&data)?,
+            None => Vec::new(),
+        })
+    }
+
+    /// Merges newly fetched transactions into the history, returning how many were new.
+    pub fn add_transactions(
+        &mut self,
+        title: &str,
+        fetched: Vec<Transaction>,
+    ) -> Result<usize, StoreError> {
+        let mut history = self.fetch_transactions(title)?;
+        let known = history.len();
+        for t in fetched {
+            if !history.iter().any(|h| h.id == t.id) {
+                history.push(t);
+            }
+        }
+        history.sort_by_key(|t| t.date);
+        self.transactions
+            .insert(title, bincode::serialize(&history).unwrap())?;
+        Ok(history.len() - known)
+    }
+
+    pub fn fetch_shopping_list(&self, user: u64) -> Result<Vec<String>, StoreError> {
+        Ok(match self.shopping.get(user.to_be_bytes())? {
+            Some(data) => bincode::deserialize(&data)?,
+            None => Vec::new(),
+        })
+    }
+
+    /// Shopping lists of all users that have one.
+    pub fn fetch_shopping_lists(&self) -> Result<Vec<(u64, Vec<String>)>, StoreError> {
+        self.shopping
+            .iter()
+            .map(|e| {
+                let (user, items) = e?;
+                Ok((user_id(&user)?, bincode::deserialize(&items)?))
+            })
+            .collect()
+    }
+
+    pub fn save_shopping_list(&mut self, user: u64, items: &[String]) -> Result<(), StoreError> {
+        if items.is_empty() {
+            self.shopping.remove(user.to_be_bytes())?;
+        } else {
+            self.shopping
+                .insert(user.to_be_bytes(), bincode::serialize(items).unwrap())?;
+        }
+        Ok(())
+    }
+
+    /// Preferences that can't be read are replaced with the defaults,
+    /// so the user can still use the bot and pick them again.
+    pub fn fetch_preferences(&self, user: u64) -> Result<Preferences, StoreError> {
+        Ok(match self.preferences.get(user.to_be_bytes())? {
+            Some(data) => bincode::deserialize(&data).unwrap_or_else(|e| {
+                log::warn!("invalid preferences of {user}, using the defaults: {e}");
+                Preferences::default()
+            }),
+            None => Preferences::default(),
+        })
+    }
+
+    pub fn save_preferences(&mut self, user: u64, prefs: &Preferences) -> Result<(), StoreError> {
+        self.preferences
+            .insert(user.to_be_bytes(), bincode::serialize(prefs).unwrap())?;
+        Ok(())
+    }
+
+    pub fn fetch_outbox(&self, user: u64) -> Result<Outbox, StoreError> {
+        Ok(match self.outbox.get(user.to_be_bytes())? {
+            Some(data) => bincode::deserialize(&data)?,
+            None => Outbox::default(),
+        })
+    }
+
+    pub fn fetch_outboxes(&self) -> Result<Vec<(u64, Outbox)>, StoreError> {
+        self.outbox
+            .iter()
+            .map(|e| {
+                let (user, outbox) = e?;
+                Ok((user_id(&user)?, bincode::deserialize(&outbox)?))
+            })
+            .collect()
+    }
+
+    pub fn save_outbox(&mut self, user: u64, outbox: &Outbox) -> Result<(), StoreError> {
+        self.outbox
+            .insert(user.to_be_bytes(), bincode::serialize(outbox).unwrap())?;
+        Ok(())
+    }
+
+    pub fn set_profile(&mut self, title: &str, profile: &str) -> Result<(), StoreError> {
+        if !self.accounts.contains_key(title)? {
+            return Err(StoreError::NoAccount);
+        }
+        self.profiles.insert(title, profile)?;
+        Ok(())
+    }
+
+    fn fetch_cookies(&self, title: &str) -> Result<CookieJar, StoreError> {
+        Ok(match self.cookies.get(title)? {
+            Some(jar) => bincode::deserialize(&jar)?,
+            None => CookieJar::default(),
+        })
+    }
+
+    /// Merges the cookies the api set into the stored jars, skipping accounts
+    /// removed in the meantime. Only the changes are saved, as other requests
+    /// may have rotated cookies since the jars were loaded.
+    pub fn save_cookies(&mut self, jars: &[(String, CookieJar)]) -> Result<(), StoreError> {
+        (&self.accounts, &self.cookies).transaction(|(accounts, cookies)| {
+            for (title, jar) in jars {
+                if accounts.get(title.as_str())?.is_none() {
+                    continue;
+                }
+                // a broken jar only costs a new session
+                let mut stored: CookieJar = match cookies.get(title.as_str())? {
+                    Some(data) => bincode::deserialize(&data).unwrap_or_default(),
+                    None => CookieJar::default(),
+                };
+                stored.merge(jar);
+                cookies.insert(title.as_str(), bincode::serialize(&stored).unwrap())?;
+            }
+            Ok::<_, ConflictableTransactionError<StoreError>>(())
+        })?;
+        Ok(())
     }
 
     pub fn remove_account(&mut self, title: &str) -> Result<AuthenticatedUser, StoreError> {
+        for tree in self.account_data() {
+            tree.remove(title)?;
+        }
         self.accounts
             .remove(title)?
-            .ok_or(StoreError("No account with that name".to_string()))
+            .ok_or(StoreError::NoAccount)
             .map(|e| bincode::deserialize::<AuthenticatedUser>(&e).map_err(|e| e.into()))?
     }
 
     pub fn rename_account(&mut self, old: &str, new: &str) -> Result<(), StoreError> {
-        self.accounts.insert(
-            new,
-            self.accounts
-                .remove(old)?
-                .ok_or(StoreError("no account with that name".to_string()))?,
-        )?;
+        validate_title(new)?;
+        if self.accounts.contains_key(new)? {
+            return Err(StoreError::AccountExists);
+        }
+        let mut trees = vec![&self.accounts];
+        trees.extend(self.account_data());
+        trees[..].transaction(|trees| {
+            let Some(data) = trees[0].remove(old)? else {
+                return abort(StoreError::NoAccount);
+            };
+            // accounts saved before validation existed are normalised on the way
+            let user = match bincode::deserialize(&data) {
+                Ok(user) => user,
+                Err(e) => return abort(e.into()),
+            };
+            let user = match validate_account(user) {
+                Ok(user) => user,
+                Err(e) => return abort(StoreError::InvalidStored(old.to_string(), e)),
+            };
+            trees[0].insert(new, bincode::serialize(&user).unwrap())?;
+            for tree in &trees[1..] {
+                if let Some(data) = tree.remove(old)? {
+                    tree.insert(new, data)?;
+                }
+            }
+            Ok(())
+        })?;
         Ok(())
     }
 }
 
-// TODO: use errors based on an enum, not a string
 #[derive(Debug)]
-pub struct StoreError(String);
+pub enum StoreError {
+    NoAccount,
+    AccountExists,
+    Invalid(ValidationError),
+    /// Title of an account saved before validation existed and what's wrong with it.
+    InvalidStored(String, ValidationError),
+    /// Path of a database another process has open.
+    Locked(String),
+    Database(String),
+}
+
+fn user_id(key: &[u8]) -> Result<u64, StoreError> {
+    Ok(u64::from_be_bytes(key.try_into().map_err(|_| {
+        StoreError::Database("invalid user id key".to_string())
+    })?))
+}
+
+impl Localize for StoreError {
+    fn localize(&self, lang: Lang) -> String {
+        let texts = lang.texts();
+        match self {
+            StoreError::NoAccount => texts.no_such_account.to_string(),
+            StoreError::AccountExists => texts.account_exists.to_string(),
+            StoreError::Invalid(e) => e.localize(lang),
+            StoreError::InvalidStored(title, e) => {
+                fill(texts.invalid_stored_account, &[title, &e.localize(lang)])
+            }
+            StoreError::Locked(path) => fill(texts.database_locked, &[path]),
+            StoreError::Database(e) => fill(texts.database_error, &[e]),
+        }
+    }
+}
+
+impl std::fmt::Display for StoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.localize(Lang::En))
+    }
+}
 
 impl From<sled::Error> for StoreError {
     fn from(e: sled::Error) -> Self {
-        Self(format!("{:?}", e))
+        Self::Database(format!("{:?}", e))
+    }
+}
+
+impl From<TransactionError<StoreError>> for StoreError {
+    fn from(e: TransactionError<StoreError>) -> Self {
+        match e {
+            TransactionError::Abort(e) => e,
+            TransactionError::Storage(e) => e.into(),
+        }
+    }
+}
+
+impl From<ValidationError> for StoreError {
+    fn from(e: ValidationError) -> Self {
+        Self::Invalid(e)
     }
 }
 
 impl From<bincode::Error> for StoreError {
     fn from(e: bincode::Error) -> Self {
-        Self(format!("{:?}", e))
+        Self::Database(format!("{:?}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake::account;
+    use reqwest::header::{HeaderMap, HeaderValue, SET_COOKIE};
+
+    fn set_cookie(cookie: &'static str) -> HeaderMap {
+        HeaderMap::from_iter([(SET_COOKIE, HeaderValue::from_static(cookie))])
+    }
+
+    #[test]
+    fn renames_accounts_with_their_data() {
+        let mut store = BiedStore::temporary();
+        // stored before validation existed
+        let mut legacy = account("a");
+        legacy.phone_number = "500 600 700".to_string();
+        store
+            .accounts
+            .insert("anna", bincode::serialize(&legacy).unwrap())
+            .unwrap();
+        let mut invalid = account("e");
+        invalid.card_number = "123".to_string();
+        store
+            .accounts
+            .insert("ewa", bincode::serialize(&invalid).unwrap())
+            .unwrap();
+        store
+            .save_activated("anna", &HashSet::from(["1001".to_string()]))
+            .unwrap();
+        store.insert_account("jan", account("j")).unwrap();
+        // a broken cookie jar doesn't hide the account
+        store.cookies.insert("jan", &[0xff][..]).unwrap();
+
+        assert!(store.rename_account("anna", "jan").is_err());
+        assert!(store.rename_account("ola", "ala").is_err());
+        let e = store.rename_account("ewa", "ela").unwrap_err();
+        assert!(e.to_string().contains("invalid card number"), "{e}");
+        store.rename_account("anna", "ania").unwrap();
+        let titles = store
+            .fetch_accounts()
+            .into_iter()
+            .map(|(title, _)| title)
+            .collect::<Vec<_>>();
+        assert_eq!(titles, ["ania", "ewa", "jan"]);
+        assert_eq!(store.account_titles(), titles);
+        assert_eq!(
+            store.fetch_account("ania").unwrap().phone_number,
+            "+48500600700"
+        );
+        assert!(store.fetch_activated("ania").unwrap().contains("1001"));
+        assert!(store.fetch_activated("anna").unwrap().is_empty());
+    }
+
+    #[test]
+    fn keeps_cookies_rotated_by_other_requests() {
+        let mut store = BiedStore::temporary();
+        store.insert_account("anna", account("a")).unwrap();
+        // two requests with the jar as it was when they started
+        let mut first = store.fetch_account("anna").unwrap().auth.cookies;
+        let mut second = first.clone();
+        first.update(&set_cookie("nr1Users=rotated"));
+        second.update(&set_cookie("session=abc"));
+        store
+            .save_cookies(&[("anna".to_string(), first.clone())])
+            .unwrap();
+        store
+            .save_cookies(&[("anna".to_string(), second), ("ewa".to_string(), first)])
+            .unwrap();
+
+        let jar = store.fetch_account("anna").unwrap().auth.cookies;
+        assert_eq!(
+            jar.iter().collect::<Vec<_>>(),
+            [("nr1Users", "rotated"), ("session", "abc")]
+        );
+        assert!(store.cookies.get("ewa").unwrap().is_none());
+    }
+
+    #[test]
+    fn stores_transactions_incrementally() {
+        let receipt = |id: &str, date: &str| Transaction {
+            id: id.to_string(),
+            date: date.parse().unwrap(),
+            store: "Warszawa".to_string(),
+            total: 1000,
+            savings: 100,
+        };
+        let mut store = BiedStore::temporary();
+        let first = vec![receipt("b", "2022-10-12"), receipt("a", "2022-10-10")];
+        assert_eq!(store.add_transactions("anna", first).unwrap(), 2);
+        // fetched since the last known day, which is included
+        let overlapping = vec![receipt("c", "2022-10-14"), receipt("b", "2022-10-12")];
+        assert_eq!(
+            store.add_transactions("anna", overlapping.clone()).unwrap(),
+            1
+        );
+        assert_eq!(store.add_transactions("anna", overlapping).unwrap(), 0);
+
+        let ids = store
+            .fetch_transactions("anna")
+            .unwrap()
+            .into_iter()
+            .map(|t| t.id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["a", "b", "c"]);
+        assert!(store.fetch_transactions("ewa").unwrap().is_empty());
+    }
+
+    #[test]
+    fn reports_a_database_open_elsewhere_as_locked() {
+        let dir = std::env::temp_dir().join(format!("biedbot-db-{}", std::process::id()));
+        let running = BiedStore::new(&dir).unwrap();
+        assert!(matches!(BiedStore::new(&dir), Err(StoreError::Locked(_))));
+        drop(running);
+        std::fs::remove_dir_all(&dir).unwrap();
     }
 }
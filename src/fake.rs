@@ -0,0 +1,148 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use reqwest::header::{HeaderMap, HeaderValue, SET_COOKIE};
+
+use crate::{
+    api::{
+        parse_account_summary, parse_offers, parse_transactions, AccountSummary, ApiError,
+        AuthData, AuthenticatedUser, Offer, OfferSource, Transaction,
+    },
+    cache::{BiedCache, SyncRequest},
+    secrets::{CacheConfig, DEFAULT_PROFILE},
+};
+
+pub const OFFERS_FIXTURE: &str = include_str!("fixtures/offers.json");
+pub const ACCOUNT_FIXTURE: &str = include_str!("fixtures/account.json");
+pub const TRANSACTIONS_FIXTURE: &str = include_str!("fixtures/transactions.json");
+
+pub enum FakeResponse {
+    Offers(String),
+    AuthExpired,
+    Network,
+}
+
+/// Serves canned responses keyed by the csrf token of the account.
+#[derive(Default)]
+pub struct FakeSource {
+    responses: HashMap<String, FakeResponse>,
+    calls: AtomicUsize,
+    /// Offers that can't be activated.
+    rejected: HashSet<String>,
+}
+
+impl FakeSource {
+    pub fn with(mut self, csrf_token: &str, response: FakeResponse) -> Self {
+        self.responses.insert(csrf_token.to_string(), response);
+        self
+    }
+
+    pub fn rejecting(mut self, offer_id: &str) -> Self {
+        self.rejected.insert(offer_id.to_string());
+        self
+    }
+
+    pub fn calls(&self) -> usize {
+        self.calls.load(Ordering::SeqCst)
+    }
+
+    fn respond<T>(
+        &self,
+        auth: &AuthData,
+        ok: impl FnOnce(&str) -> Result<T, ApiError>,
+    ) -> Result<T, ApiError> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        match self.responses.get(&auth.csrf_token) {
+            Some(FakeResponse::Offers(body)) => ok(body),
+            Some(FakeResponse::AuthExpired) => Err(ApiError::AuthExpired),
+            Some(FakeResponse::Network) | None => {
+                Err(ApiError::Network("connection refused".to_string()))
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl OfferSource for FakeSource {
+    async fn get_offers(
+        &self,
+        _profile: &str,
+        auth: &mut AuthData,
+    ) -> Result<Vec<Offer>, ApiError> {
+        self.respond(auth, parse_offers)
+    }
+
+    async fn get_account_summary(
+        &self,
+        _profile: &str,
+        auth: &mut AuthData,
+    ) -> Result<AccountSummary, ApiError> {
+        self.respond(auth, |_| parse_account_summary(ACCOUNT_FIXTURE))
+    }
+
+    async fn get_transactions(
+        &self,
+        _profile: &str,
+        auth: &mut AuthData,
+        since: Option<NaiveDate>,
+    ) -> Result<Vec<Transaction>, ApiError> {
+        self.respond(auth, |_| {
+            let mut transactions = parse_transactions(TRANSACTIONS_FIXTURE)?;
+            transactions.retain(|t| since.is_none_or(|since| t.date >= since));
+            Ok(transactions)
+        })
+    }
+
+    async fn activate_offer(
+        &self,
+        _profile: &str,
+        auth: &mut AuthData,
+        offer_id: &str,
+    ) -> Result<(), ApiError> {
+        self.respond(auth, |_| Ok(()))?;
+        if self.rejected.contains(offer_id) {
+            return Err(ApiError::Decode(format!(
+                "offer {offer_id} can't be activated"
+            )));
+        }
+        // the session rotates with every activation
+        let cookie = HeaderValue::from_str(&format!("session={offer_id}")).unwrap();
+        auth.cookies
+            .update(&HeaderMap::from_iter([(SET_COOKIE, cookie)]));
+        Ok(())
+    }
+}
+
+pub fn account(csrf_token: &str) -> AuthenticatedUser {
+    AuthenticatedUser {
+        phone_number: "+48500600700".to_string(),
+        card_number: "5901234123457".to_string(),
+        auth: AuthData {
+            users1: "users1".to_string(),
+            users2: "users2".to_string(),
+            csrf_token: csrf_token.to_string(),
+            cookies: Default::default(),
+        },
+        profile: DEFAULT_PROFILE.to_string(),
+    }
+}
+
+/// A cache synced with the offer fixture on every account, using the titles as tokens.
+pub async fn synced_cache(titles: &[&str]) -> BiedCache {
+    let api = titles.iter().fold(FakeSource::default(), |api, title| {
+        api.with(title, FakeResponse::Offers(OFFERS_FIXTURE.to_string()))
+    });
+    let accounts = titles
+        .iter()
+        .map(|title| (title.to_string(), account(title)))
+        .collect();
+    let cache = BiedCache::new(&CacheConfig::default());
+    cache
+        .sync_offers(accounts, &api, SyncRequest::default())
+        .await;
+    cache
+}
@@ -0,0 +1,371 @@
+use std::{fmt::Display, str::FromStr};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::{
+    api::Offer,
+    i18n::{fill, Lang, Localize},
+    search::tokenize,
+};
+
+lazy_static! {
+    static ref PRICE: Regex = Regex::new(r"^(\d+)(?:[.,](\d{1,2}))?\s*(?:zł)?$").unwrap();
+}
+
+/// Parses a price with an optional `zł` into grosze, e.g. `"4,99 zł"` -> `499`.
+pub fn parse_price(price: &str) -> Option<u32> {
+    let caps = PRICE.captures(price.trim())?;
+    let whole: u32 = caps.get(1)?.as_str().parse().ok()?;
+    let fraction = match caps.get(2).map(|e| e.as_str()) {
+        Some(f) if f.len() == 1 => f.parse::<u32>().ok()? * 10,
+        Some(f) => f.parse().ok()?,
+        None => 0,
+    };
+    whole.checked_mul(100)?.checked_add(fraction)
+}
+
+/// Formats grosze back into a price, e.g. `499` -> `"4,99zł"`.
+pub fn format_price(grosze: u32) -> String {
+    format!("{},{:02}zł", grosze / 100, grosze % 100)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Category {
+    Dairy,
+    Meat,
+    Bakery,
+    Produce,
+    Drinks,
+    Alcohol,
+    Sweets,
+    Household,
+    Other,
+}
+
+const CATEGORY_KEYWORDS: [(Category, &[&str]); 8] = [
+    (
+        Category::Dairy,
+        &[
+            "mleko", "ser", "jogurt", "maslo", "smietan", "twarog", "kefir", "serek", "jaja",
+        ],
+    ),
+    (
+        Category::Meat,
+        &[
+            "mieso", "kurczak", "wieprz", "wolow", "szynka", "kielbas", "parowk", "boczek",
+            "filet", "ryba", "losos",
+        ],
+    ),
+    (
+        Category::Bakery,
+        &["chleb", "bulk", "bagiet", "rogal", "pieczyw", "drozdzowk"],
+    ),
+    (
+        Category::Produce,
+        &[
+            "jablk", "banan", "pomidor", "ziemniak", "ogor", "marchew", "cebul", "owoc", "warzyw",
+            "salat", "papryk",
+        ],
+    ),
+    (
+        Category::Alcohol,
+        &["piwo", "wino", "wodka", "whisky", "likier", "cydr"],
+    ),
+    (
+        Category::Drinks,
+        &["woda", "sok", "napoj", "kawa", "herbat", "cola", "nektar"],
+    ),
+    (
+        Category::Sweets,
+        &[
+            "czekolad", "baton", "cukierk", "ciastk", "lody", "wafel", "chips", "zelk",
+        ],
+    ),
+    (
+        Category::Household,
+        &[
+            "proszek",
+            "plyn",
+            "papier",
+            "recznik",
+            "szampon",
+            "mydlo",
+            "pasta",
+            "kapsulk",
+            "chusteczk",
+        ],
+    ),
+];
+
+impl Category {
+    pub fn of(offer: &Offer) -> Self {
+        let tokens = tokenize(&offer.name);
+        CATEGORY_KEYWORDS
+            .iter()
+            .find(|(_, keywords)| {
+                tokens
+                    .iter()
+                    .any(|t| keywords.iter().any(|k| t.starts_with(k)))
+            })
+            .map(|(c, _)| *c)
+            .unwrap_or(Category::Other)
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Category::Dairy => "dairy",
+            Category::Meat => "meat",
+            Category::Bakery => "bakery",
+            Category::Produce => "produce",
+            Category::Drinks => "drinks",
+            Category::Alcohol => "alcohol",
+            Category::Sweets => "sweets",
+            Category::Household => "household",
+            Category::Other => "other",
+        }
+    }
+}
+
+impl FromStr for Category {
+    type Err = FilterError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        CATEGORY_KEYWORDS
+            .iter()
+            .map(|(c, _)| *c)
+            .chain([Category::Other])
+            .find(|c| c.name() == s)
+            .ok_or_else(|| FilterError::UnknownCategory(s.to_string()))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum SortMode {
+    #[default]
+    Api,
+    Discount,
+    Price,
+    Name,
+}
+
+impl SortMode {
+    pub const ALL: [SortMode; 4] = [
+        SortMode::Api,
+        SortMode::Discount,
+        SortMode::Price,
+        SortMode::Name,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            SortMode::Api => "default",
+            SortMode::Discount => "discount",
+            SortMode::Price => "price",
+            SortMode::Name => "name",
+        }
+    }
+}
+
+impl FromStr for SortMode {
+    type Err = FilterError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SortMode::ALL
+            .into_iter()
+            .find(|m| m.name() == s)
+            .ok_or_else(|| FilterError::UnknownSort(s.to_string()))
+    }
+}
+
+impl Display for SortMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Options of the `/offers` command, e.g. `sort:discount min:30% max:10zł cat:dairy`.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct OfferFilter {
+    pub sort: SortMode,
+    pub min_discount: Option<i32>,
+    pub max_price: Option<u32>,
+    pub category: Option<Category>,
+}
+
+impl OfferFilter {
+    pub fn matches(&self, offer: &Offer) -> bool {
+        self.min_discount
+            .is_none_or(|min| offer.discount_percent >= min)
+            && self
+                .max_price
+                .is_none_or(|max| offer.price().is_some_and(|p| p <= max))
+            && self.category.is_none_or(|c| Category::of(offer) == c)
+    }
+
+    pub fn apply<'a>(&self, offers: &'a [Offer]) -> Vec<&'a Offer> {
+        let mut offers = offers
+            .iter()
+            .filter(|o| self.matches(o))
+            .collect::<Vec<_>>();
+        match self.sort {
+            SortMode::Api => {}
+            SortMode::Discount => offers.sort_by_key(|o| std::cmp::Reverse(o.discount_percent)),
+            SortMode::Price => offers.sort_by_key(|o| o.price().unwrap_or(u32::MAX)),
+            SortMode::Name => offers.sort_by_key(|o| o.name.to_lowercase()),
+        }
+        offers
+    }
+
+    pub fn with_sort(&self, sort: SortMode) -> Self {
+        Self {
+            sort,
+            ..self.clone()
+        }
+    }
+}
+
+impl FromStr for OfferFilter {
+    type Err = FilterError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut filter = OfferFilter::default();
+        for arg in s.split_whitespace() {
+            let (key, value) = arg
+                .split_once(':')
+                .ok_or_else(|| FilterError::NotKeyValue(arg.to_string()))?;
+            match key {
+                "sort" => filter.sort = value.parse()?,
+                "min" => {
+                    filter.min_discount = Some(
+                        value
+                            .trim_end_matches('%')
+                            .parse()
+                            .ok()
+                            .filter(|min| (0..=100).contains(min))
+                            .ok_or_else(|| FilterError::InvalidDiscount(value.to_string()))?,
+                    )
+                }
+                "max" => {
+                    filter.max_price = Some(
+                        parse_price(value)
+                            .ok_or_else(|| FilterError::InvalidPrice(value.to_string()))?,
+                    )
+                }
+                "cat" => filter.category = Some(value.parse()?),
+                _ => return Err(FilterError::UnknownOption(key.to_string())),
+            }
+        }
+        Ok(filter)
+    }
+}
+
+impl Display for OfferFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut args = vec![format!("sort:{}", self.sort)];
+        if let Some(min) = self.min_discount {
+            args.push(format!("min:{min}%"));
+        }
+        if let Some(max) = self.max_price {
+            args.push(format!("max:{}", format_price(max)));
+        }
+        if let Some(c) = self.category {
+            args.push(format!("cat:{}", c.name()));
+        }
+        write!(f, "{}", args.join(" "))
+    }
+}
+
+/// Each variant holds the part of the options that couldn't be used.
+#[derive(Debug)]
+pub enum FilterError {
+    NotKeyValue(String),
+    UnknownSort(String),
+    InvalidDiscount(String),
+    InvalidPrice(String),
+    UnknownCategory(String),
+    UnknownOption(String),
+}
+
+impl Localize for FilterError {
+    fn localize(&self, lang: Lang) -> String {
+        let texts = lang.texts();
+        match self {
+            FilterError::NotKeyValue(arg) => fill(texts.filter_key_value, &[arg]),
+            FilterError::UnknownSort(s) => fill(texts.unknown_sort, &[s]),
+            FilterError::InvalidDiscount(s) => fill(texts.invalid_discount, &[s]),
+            FilterError::InvalidPrice(s) => fill(texts.invalid_price, &[s]),
+            FilterError::UnknownCategory(s) => {
+                let names = CATEGORY_KEYWORDS
+                    .iter()
+                    .map(|(c, _)| c.name())
+                    .chain([Category::Other.name()])
+                    .collect::<Vec<_>>();
+                fill(texts.unknown_category, &[s, &names.join(", ")])
+            }
+            FilterError::UnknownOption(key) => fill(texts.unknown_option, &[key]),
+        }
+    }
+}
+
+impl Display for FilterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.localize(Lang::En))
+    }
+}
+
+impl std::error::Error for FilterError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_prices() {
+        assert_eq!(parse_price("4,99 zł"), Some(499));
+        assert_eq!(parse_price("4.5"), Some(450));
+        assert_eq!(parse_price("10zł"), Some(1000));
+        assert_eq!(parse_price(" 2,49 "), Some(249));
+        assert_eq!(parse_price("zł"), None);
+        assert_eq!(parse_price("4,999"), None);
+        assert_eq!(parse_price("99999999zł"), None);
+        assert_eq!(format_price(499), "4,99zł");
+    }
+
+    #[test]
+    fn round_trips_filters() {
+        let filter = "cat:dairy max:10zł min:30% sort:price"
+            .parse::<OfferFilter>()
+            .unwrap();
+        assert_eq!(
+            filter,
+            OfferFilter {
+                sort: SortMode::Price,
+                min_discount: Some(30),
+                max_price: Some(1000),
+                category: Some(Category::Dairy),
+            }
+        );
+        assert_eq!(
+            filter.to_string(),
+            "sort:price min:30% max:10,00zł cat:dairy"
+        );
+        assert_eq!(filter.to_string().parse::<OfferFilter>().unwrap(), filter);
+        assert_eq!(OfferFilter::default().to_string(), "sort:default");
+
+        for invalid in [
+            "sort",
+            "sort:best",
+            "min:lots",
+            "min:-2147483648%",
+            "min:101%",
+            "max:99999999zł",
+            "max:abc10",
+            "max:1x2",
+            "cat:toys",
+            "page:2",
+        ] {
+            assert!(invalid.parse::<OfferFilter>().is_err(), "{invalid}");
+        }
+    }
+}
@@ -0,0 +1,559 @@
+use std::{fmt::Display, str::FromStr};
+
+use chrono::{DateTime, Utc, Weekday};
+use chrono_tz::Europe::Warsaw;
+use serde::{Deserialize, Serialize};
+
+use crate::spending::Month;
+
+/// Language of the bot replies, picked with `/language` or `/settings`.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Lang {
+    #[default]
+    En,
+    Pl,
+}
+
+impl Lang {
+    pub const ALL: [Lang; 2] = [Lang::En, Lang::Pl];
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Lang::En => "en",
+            Lang::Pl => "pl",
+        }
+    }
+
+    /// Language for a telegram `language_code` like `pl` or `en-US`.
+    pub fn from_telegram(code: Option<&str>) -> Self {
+        match code {
+            Some(code) if code.starts_with("pl") => Lang::Pl,
+            _ => Lang::En,
+        }
+    }
+
+    pub fn texts(self) -> &'static Texts {
+        match self {
+            Lang::En => &EN,
+            Lang::Pl => &PL,
+        }
+    }
+
+    /// Formats grosze, e.g. `499` -> `"4,99 zł"` or `"PLN 4.99"`.
+    pub fn price(self, grosze: impl Into<i64>) -> String {
+        let grosze = grosze.into();
+        let sign = if grosze < 0 { "-" } else { "" };
+        let (whole, fraction) = (grosze.unsigned_abs() / 100, grosze.unsigned_abs() % 100);
+        match self {
+            Lang::En => format!("{sign}PLN {whole}.{fraction:02}"),
+            Lang::Pl => format!("{sign}{whole},{fraction:02} zł"),
+        }
+    }
+
+    /// Formats a point in time as seen in Poland.
+    pub fn datetime(self, t: DateTime<Utc>) -> String {
+        let t = t.with_timezone(&Warsaw);
+        match self {
+            Lang::En => t.format("%b %-d, %H:%M").to_string(),
+            Lang::Pl => t.format("%d.%m %H:%M").to_string(),
+        }
+    }
+
+    pub fn month(self, m: Month) -> String {
+        let names = match self {
+            Lang::En => &EN_MONTHS,
+            Lang::Pl => &PL_MONTHS,
+        };
+        format!("{} {}", names[m.month as usize - 1], m.year)
+    }
+
+    pub fn weekday(self, day: Weekday) -> &'static str {
+        let names = match self {
+            Lang::En => &EN_WEEKDAYS,
+            Lang::Pl => &PL_WEEKDAYS,
+        };
+        names[day.num_days_from_monday() as usize]
+    }
+}
+
+impl FromStr for Lang {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Lang::ALL
+            .into_iter()
+            .find(|l| l.code() == s.trim().to_lowercase())
+            .ok_or_else(|| format!("unknown language `{s}`"))
+    }
+}
+
+impl Display for Lang {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.code())
+    }
+}
+
+/// Errors shown to the bot users. Their `Display` is the English text, for logs and the cli.
+pub trait Localize {
+    fn localize(&self, lang: Lang) -> String;
+}
+
+/// Substitutes the `{}` of a catalog message in order.
+pub fn fill(template: &str, args: &[&(dyn Display + Sync)]) -> String {
+    let mut parts = template.split("{}");
+    let mut text = parts.next().unwrap_or_default().to_string();
+    for (i, part) in parts.enumerate() {
+        if let Some(arg) = args.get(i) {
+            text.push_str(&arg.to_string());
+        }
+        text.push_str(part);
+    }
+    text
+}
+
+/// Text from the api made safe for `ParseMode::Html` messages, attributes included.
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+const EN_MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+const PL_MONTHS: [&str; 12] = [
+    "styczeń",
+    "luty",
+    "marzec",
+    "kwiecień",
+    "maj",
+    "czerwiec",
+    "lipiec",
+    "sierpień",
+    "wrzesień",
+    "październik",
+    "listopad",
+    "grudzień",
+];
+
+const EN_WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+const PL_WEEKDAYS: [&str; 7] = ["pon.", "wt.", "śr.", "czw.", "pt.", "sob.", "niedz."];
+
+/// Messages of the bot, `{}` are filled with [`fill`].
+pub struct Texts {
+    /// Replaces the command descriptions in `/help`, if set.
+    pub help: Option<&'static str>,
+    /// Replaces the admin command descriptions in `/help`, if set.
+    pub admin_help: Option<&'static str>,
+    pub unknown_message: &'static str,
+    pub no_account: &'static str,
+    pub offers_usage: &'static str,
+    pub current_offers: &'static str,
+    pub synced_at: &'static str,
+    pub offer_unavailable: &'static str,
+    pub no_account_offers: &'static str,
+    pub activate: &'static str,
+    pub active: &'static str,
+    pub activate_all: &'static str,
+    pub offer_activated: &'static str,
+    pub activation_failed: &'static str,
+    pub activations_failed: &'static str,
+    pub activation_summary: &'static str,
+    pub failed_count: &'static str,
+    pub search_usage: &'static str,
+    pub no_search_results: &'static str,
+    pub view_card: &'static str,
+    pub no_accounts_to_sync: &'static str,
+    pub synced_accounts: &'static str,
+    pub synced_cached_accounts: &'static str,
+    pub account_synced: &'static str,
+    pub account_fresh: &'static str,
+    pub summary: &'static str,
+    pub summary_as_of: &'static str,
+    pub spending_in: &'static str,
+    pub spending: &'static str,
+    pub together: &'static str,
+    pub history_not_saved: &'static str,
+    pub history_not_updated: &'static str,
+    pub list_add_usage: &'static str,
+    pub list_added: &'static str,
+    pub list_add_failed: &'static str,
+    pub list_remove_usage: &'static str,
+    pub list_removed: &'static str,
+    pub list_remove_failed: &'static str,
+    pub shopping_list: &'static str,
+    pub shopping_list_empty: &'static str,
+    pub no_item_offers: &'static str,
+    pub basket_usage: &'static str,
+    pub basket_error: &'static str,
+    pub no_basket_offers: &'static str,
+    pub basket_card: &'static str,
+    pub over_limits: &'static str,
+    pub basket_total: &'static str,
+    pub single_card: &'static str,
+    pub missing_offers: &'static str,
+    pub language_usage: &'static str,
+    pub language_set: &'static str,
+    pub settings: &'static str,
+    pub setting_language: &'static str,
+    pub language_auto: &'static str,
+    pub setting_display: &'static str,
+    pub display_photos: &'static str,
+    pub display_compact: &'static str,
+    pub setting_min_discount: &'static str,
+    pub any_discount: &'static str,
+    pub setting_digest: &'static str,
+    pub setting_quiet_hours: &'static str,
+    pub off: &'static str,
+    pub setting_default_account: &'static str,
+    pub all_accounts: &'static str,
+    pub offer_alert: &'static str,
+    pub notifications: &'static str,
+    pub details: &'static str,
+    pub offer_valid: &'static str,
+    pub offer_limit: &'static str,
+    pub offer_type: &'static str,
+    pub product_page: &'static str,
+    pub outdated_button: &'static str,
+    pub admins_only: &'static str,
+    pub account_line: &'static str,
+    pub account_points: &'static str,
+    pub account_added: &'static str,
+    pub add_failed: &'static str,
+    pub account_renamed: &'static str,
+    pub rename_failed: &'static str,
+    pub account_removed: &'static str,
+    pub remove_failed: &'static str,
+    pub profile_set: &'static str,
+    pub profile_failed: &'static str,
+    // errors, see [`Localize`]
+    pub no_such_account: &'static str,
+    pub account_exists: &'static str,
+    pub invalid_stored_account: &'static str,
+    pub database_locked: &'static str,
+    pub database_error: &'static str,
+    pub invalid_card_number: &'static str,
+    pub ean_length: &'static str,
+    pub ean_digits: &'static str,
+    pub ean_check_digit: &'static str,
+    pub image_encoding: &'static str,
+    pub phone_characters: &'static str,
+    pub phone_international: &'static str,
+    pub phone_length: &'static str,
+    pub empty_title: &'static str,
+    pub unknown_profile: &'static str,
+    pub filter_key_value: &'static str,
+    pub unknown_sort: &'static str,
+    pub invalid_discount: &'static str,
+    pub invalid_price: &'static str,
+    pub unknown_category: &'static str,
+    pub unknown_option: &'static str,
+    pub invalid_month: &'static str,
+    pub invalid_quantity: &'static str,
+    pub invalid_item: &'static str,
+    pub too_many_items: &'static str,
+    pub auth_expired: &'static str,
+    pub network_error: &'static str,
+    pub decode_error: &'static str,
+    pub api_config_error: &'static str,
+}
+
+static EN: Texts = Texts {
+    help: None,
+    admin_help: None,
+    unknown_message: "Unable to handle the message. Type /help to see the usage.",
+    no_account: "No account named {}.",
+    offers_usage: "{}\nUsage: /offers sort:discount min:30% max:10zł cat:dairy",
+    current_offers: "Current offers ({}):",
+    synced_at: " (synced {})",
+    offer_unavailable: "This offer is no longer available.",
+    no_account_offers: "This account has no offers, try /sync.",
+    activate: "Activate",
+    active: "\u{2705} Active",
+    activate_all: "Activate all",
+    offer_activated: "Offer activated.",
+    activation_failed: "Couldn't activate the offer: {}",
+    activations_failed: "Couldn't activate the offers: {}",
+    activation_summary: "{} of {} offers active.",
+    failed_count: "{} failed: {}",
+    search_usage: "Usage: /search text",
+    no_search_results: "No offers matching \"{}\".",
+    view_card: "View card",
+    no_accounts_to_sync: "No accounts to sync.",
+    synced_accounts: "Synced {} of {} accounts:",
+    synced_cached_accounts: "Synced {} of {} accounts, {} up to date in the cache:",
+    account_synced: "{}: ok, {} offers",
+    account_fresh: "{}: up to date, {} offers",
+    summary: "{} points, {} tier ({}, {})",
+    summary_as_of: "{} (as of {}, {})",
+    spending_in: "Spending in {}:",
+    spending: "{} in {} visits, saved {} on promotions",
+    together: "Together: {}",
+    history_not_saved: " (history not saved: {})",
+    history_not_updated: " (history not updated: {})",
+    list_add_usage: "Usage: /list_add item",
+    list_added: "Added {} to your shopping list.",
+    list_add_failed: "Error adding the item: {}",
+    list_remove_usage: "Usage: /list_remove number, see /shopping for the numbers.",
+    list_removed: "Removed {} from your shopping list.",
+    list_remove_failed: "Error removing the item: {}",
+    shopping_list: "Shopping list:",
+    shopping_list_empty: "Your shopping list is empty, add items with /list_add.",
+    no_item_offers: "no offers",
+    basket_usage: "Usage: /basket item [xN], ... or add items with /list_add.",
+    basket_error: "Error: {}",
+    no_basket_offers: "No offers for this basket.",
+    basket_card: "{}: {}, saves {}",
+    over_limits: "Over the offer limits: {}",
+    basket_total: "Together: {}, saved {} vs regular prices.",
+    single_card: "With {} only it's {}, splitting saves {}.",
+    missing_offers: "No offers for: {}",
+    language_usage: "Usage: /language en|pl",
+    language_set: "Replies will be in English.",
+    settings: "Settings, tap one to change it:",
+    setting_language: "Language: {}",
+    language_auto: "automatic",
+    setting_display: "Offers: {}",
+    display_photos: "with photos",
+    display_compact: "compact",
+    setting_min_discount: "Notify from: {}",
+    any_discount: "any discount",
+    setting_digest: "Digest: {}",
+    setting_quiet_hours: "Quiet hours: {}",
+    off: "off",
+    setting_default_account: "Default account: {}",
+    all_accounts: "all",
+    offer_alert: "{}: {} (-{}%, {})",
+    notifications: "New offers for your shopping list:",
+    details: "Details",
+    offer_valid: "Valid: {}",
+    offer_limit: "Limit: {}",
+    offer_type: "Type: {}",
+    product_page: "Product page",
+    outdated_button: "This button is outdated, please use the command again.",
+    admins_only: "Only the bot admins can do that.",
+    account_line: "*{}* \\- {} profile: `{}`;{}",
+    account_points: " points: `{}`;",
+    account_added: "Account added succesfully",
+    add_failed: "Error adding account: {}",
+    account_renamed: "Renamed user {} to {}",
+    rename_failed: "Error renaming user: {}",
+    account_removed: "Removed user {}",
+    remove_failed: "Error removing user: {}",
+    profile_set: "Set the profile of {} to {}",
+    profile_failed: "Error setting the profile: {}",
+    no_such_account: "no account with that name",
+    account_exists: "an account with that name already exists",
+    invalid_stored_account: "{}: {}, add the account again with valid data before renaming it",
+    database_locked: "the database {} is locked by a running bot",
+    database_error: "database error: {}",
+    invalid_card_number: "invalid card number: {}",
+    ean_length: "EAN-13 needs 13 digits, got {}",
+    ean_digits: "EAN-13 may only contain digits",
+    ean_check_digit: "EAN-13 check digit is {}, expected {}",
+    image_encoding: "unable to encode image: {}",
+    phone_characters:
+        "invalid phone number: `{}` may only contain digits and an optional leading +",
+    phone_international: "invalid phone number: `{}` is not a valid international number",
+    phone_length: "invalid phone number: Polish numbers have {} digits, `{}` has {}",
+    empty_title: "invalid title: title can't be empty",
+    unknown_profile: "invalid profile: unknown profile `{}`, expected one of: {}",
+    filter_key_value: "expected key:value, got `{}`",
+    unknown_sort: "unknown sort mode `{}`",
+    invalid_discount: "invalid discount `{}`",
+    invalid_price: "invalid price `{}`",
+    unknown_category: "unknown category `{}`, expected one of: {}",
+    unknown_option: "unknown option `{}`",
+    invalid_month: "invalid month `{}`, expected YYYY-MM",
+    invalid_quantity: "invalid quantity \"{}\"",
+    invalid_item: "invalid item \"{}\"",
+    too_many_items: "at most x{} of \"{}\"",
+    auth_expired: "auth expired",
+    network_error: "network error: {}",
+    decode_error: "decode error: {}",
+    api_config_error: "invalid api config: {}",
+};
+
+static PL: Texts = Texts {
+    help: Some(
+        "Dostępne komendy:\n\
+         /help — wyświetla tę wiadomość.\n\
+         /offers — wszystkie oferty. Użycie: /offers sort:discount|price|name min:30% max:10zł cat:dairy\n\
+         /sync — synchronizuje oferty. Użycie: /sync [force] [nazwa]\n\
+         /search — szuka w ofertach. Użycie: /search tekst\n\
+         /list_add — dodaje produkt do listy zakupów. Użycie: /list_add mleko\n\
+         /list_remove — usuwa produkt z listy zakupów. Użycie: /list_remove numer\n\
+         /shopping — lista zakupów z pasującymi ofertami.\n\
+         /basket — najtańsze karty dla koszyka, domyślnie listy zakupów. Użycie: /basket [mleko x2, kawa]\n\
+         /language — język odpowiedzi. Użycie: /language en|pl\n\
+         /settings — ustawienia.",
+    ),
+    admin_help: Some(
+        "Komendy administratora:\n\
+         /add — dodaje konto. Użycie: /add nazwa ean telefon user1 user2 csrf\n\
+         /cancel — anuluje dodawanie konta.\n\
+         /list — wszystkie dodane konta.\n\
+         /rename — zmienia nazwę konta.\n\
+         /remove — usuwa konto o podanej nazwie.\n\
+         /profile — ustawia profil api konta. Użycie: /profile nazwa profil\n\
+         /balance — saldo punktów kart. Użycie: /balance [nazwa]\n\
+         /spending — podsumowanie wydatków kart. Użycie: /spending [nazwa] [RRRR-MM]",
+    ),
+    unknown_message: "Nie rozumiem tej wiadomości. Wpisz /help, aby zobaczyć komendy.",
+    no_account: "Nie ma konta o nazwie {}.",
+    offers_usage: "{}\nUżycie: /offers sort:discount min:30% max:10zł cat:dairy",
+    current_offers: "Aktualne oferty ({}):",
+    synced_at: " (zsynchronizowano {})",
+    offer_unavailable: "Ta oferta nie jest już dostępna.",
+    no_account_offers: "To konto nie ma ofert, spróbuj /sync.",
+    activate: "Aktywuj",
+    active: "\u{2705} Aktywna",
+    activate_all: "Aktywuj wszystkie",
+    offer_activated: "Oferta aktywowana.",
+    activation_failed: "Nie udało się aktywować oferty: {}",
+    activations_failed: "Nie udało się aktywować ofert: {}",
+    activation_summary: "Aktywne oferty: {} z {}.",
+    failed_count: "Nieudane: {}, {}",
+    search_usage: "Użycie: /search tekst",
+    no_search_results: "Brak ofert pasujących do \"{}\".",
+    view_card: "Pokaż kartę",
+    no_accounts_to_sync: "Brak kont do synchronizacji.",
+    synced_accounts: "Zsynchronizowano {} z {} kont:",
+    synced_cached_accounts: "Zsynchronizowano {} z {} kont, {} aktualnych w pamięci:",
+    account_synced: "{}: ok, ofert: {}",
+    account_fresh: "{}: aktualne, ofert: {}",
+    summary: "{} pkt, poziom {} ({}, {})",
+    summary_as_of: "{} (stan z {}, {})",
+    spending_in: "Wydatki, {}:",
+    spending: "{} podczas {} wizyt, zaoszczędzono {} na promocjach",
+    together: "Razem: {}",
+    history_not_saved: " (nie zapisano historii: {})",
+    history_not_updated: " (nie zaktualizowano historii: {})",
+    list_add_usage: "Użycie: /list_add produkt",
+    list_added: "Dodano {} do listy zakupów.",
+    list_add_failed: "Błąd dodawania produktu: {}",
+    list_remove_usage: "Użycie: /list_remove numer, numery są w /shopping.",
+    list_removed: "Usunięto {} z listy zakupów.",
+    list_remove_failed: "Błąd usuwania produktu: {}",
+    shopping_list: "Lista zakupów:",
+    shopping_list_empty: "Lista zakupów jest pusta, dodaj produkty przez /list_add.",
+    no_item_offers: "brak ofert",
+    basket_usage: "Użycie: /basket produkt [xN], ... albo dodaj produkty przez /list_add.",
+    basket_error: "Błąd: {}",
+    no_basket_offers: "Brak ofert dla tego koszyka.",
+    basket_card: "{}: {}, oszczędzasz {}",
+    over_limits: "Ponad limity ofert: {}",
+    basket_total: "Razem: {}, oszczędzasz {} względem cen regularnych.",
+    single_card: "Tylko z {} to {}, podział oszczędza {}.",
+    missing_offers: "Brak ofert dla: {}",
+    language_usage: "Użycie: /language en|pl",
+    language_set: "Odpowiedzi będą po polsku.",
+    settings: "Ustawienia, kliknij, aby zmienić:",
+    setting_language: "Język: {}",
+    language_auto: "automatyczny",
+    setting_display: "Oferty: {}",
+    display_photos: "ze zdjęciami",
+    display_compact: "kompaktowo",
+    setting_min_discount: "Powiadomienia od: {}",
+    any_discount: "każdej zniżki",
+    setting_digest: "Podsumowanie: {}",
+    setting_quiet_hours: "Cisza nocna: {}",
+    off: "wyłączone",
+    setting_default_account: "Domyślne konto: {}",
+    all_accounts: "wszystkie",
+    offer_alert: "{}: {} (-{}%, {})",
+    notifications: "Nowe oferty z listy zakupów:",
+    details: "Szczegóły",
+    offer_valid: "Ważna: {}",
+    offer_limit: "Limit: {}",
+    offer_type: "Typ: {}",
+    product_page: "Strona produktu",
+    outdated_button: "Ten przycisk jest nieaktualny, użyj komendy ponownie.",
+    admins_only: "Tylko administratorzy bota mogą to zrobić.",
+    account_line: "*{}* \\- {} profil: `{}`;{}",
+    account_points: " punkty: `{}`;",
+    account_added: "Dodano konto",
+    add_failed: "Błąd dodawania konta: {}",
+    account_renamed: "Zmieniono nazwę konta {} na {}",
+    rename_failed: "Błąd zmiany nazwy konta: {}",
+    account_removed: "Usunięto konto {}",
+    remove_failed: "Błąd usuwania konta: {}",
+    profile_set: "Ustawiono profil konta {} na {}",
+    profile_failed: "Błąd ustawiania profilu: {}",
+    no_such_account: "nie ma konta o tej nazwie",
+    account_exists: "konto o tej nazwie już istnieje",
+    invalid_stored_account: "{}: {}, dodaj konto ponownie z poprawnymi danymi przed zmianą nazwy",
+    database_locked: "baza danych {} jest zablokowana przez działającego bota",
+    database_error: "błąd bazy danych: {}",
+    invalid_card_number: "nieprawidłowy numer karty: {}",
+    ean_length: "EAN-13 wymaga 13 cyfr, podano {}",
+    ean_digits: "EAN-13 może zawierać tylko cyfry",
+    ean_check_digit: "cyfra kontrolna EAN-13 to {}, oczekiwano {}",
+    image_encoding: "nie udało się zakodować obrazu: {}",
+    phone_characters: "nieprawidłowy numer telefonu: `{}` może zawierać tylko cyfry i początkowy +",
+    phone_international: "nieprawidłowy numer telefonu: `{}` nie jest poprawnym numerem międzynarodowym",
+    phone_length: "nieprawidłowy numer telefonu: polskie numery mają {} cyfr, `{}` ma {}",
+    empty_title: "nieprawidłowa nazwa: nazwa nie może być pusta",
+    unknown_profile: "nieprawidłowy profil: nieznany profil `{}`, dostępne: {}",
+    filter_key_value: "oczekiwano klucz:wartość, podano `{}`",
+    unknown_sort: "nieznany sposób sortowania `{}`",
+    invalid_discount: "nieprawidłowa zniżka `{}`",
+    invalid_price: "nieprawidłowa cena `{}`",
+    unknown_category: "nieznana kategoria `{}`, dostępne: {}",
+    unknown_option: "nieznana opcja `{}`",
+    invalid_month: "nieprawidłowy miesiąc `{}`, oczekiwano RRRR-MM",
+    invalid_quantity: "nieprawidłowa ilość \"{}\"",
+    invalid_item: "nieprawidłowy produkt \"{}\"",
+    too_many_items: "najwyżej x{} produktu \"{}\"",
+    auth_expired: "sesja wygasła",
+    network_error: "błąd sieci: {}",
+    decode_error: "błąd odczytu odpowiedzi: {}",
+    api_config_error: "nieprawidłowa konfiguracja api: {}",
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_for_the_locale() {
+        assert_eq!(Lang::Pl.price(1999), "19,99 zł");
+        assert_eq!(Lang::En.price(405), "PLN 4.05");
+        assert_eq!(Lang::Pl.price(-1250), "-12,50 zł");
+        let t = DateTime::parse_from_rfc3339("2022-10-18T21:30:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(Lang::Pl.datetime(t), "18.10 23:30");
+        assert_eq!(Lang::En.datetime(t), "Oct 18, 23:30");
+        assert_eq!(
+            fill(Lang::Pl.texts().activation_summary, &[&2, &5]),
+            "Aktywne oferty: 2 z 5."
+        );
+        assert_eq!("PL".parse::<Lang>(), Ok(Lang::Pl));
+        assert_eq!(Lang::from_telegram(Some("en-US")), Lang::En);
+        assert_eq!(
+            escape_html("<b>\"M&M's\"</b>"),
+            "&lt;b&gt;&quot;M&amp;M's&quot;&lt;/b&gt;"
+        );
+    }
+}
@@ -1,36 +1,191 @@
+mod admin;
 mod api;
+mod barcode;
+mod basket;
 mod cache;
+mod callback;
+mod cli;
+mod cookies;
 mod db;
+#[cfg(test)]
+mod fake;
+mod filter;
+mod i18n;
+mod notify;
+mod preferences;
+mod search;
 mod secrets;
+mod shopping;
+mod spending;
+mod validation;
+mod webhook;
 
 use crate::{api::BiedApi, secrets::Secrets};
 
-use api::{AuthData, Offer};
-use cache::BiedCache;
+use api::{AuthData, AuthenticatedUser, Offer, OfferSource};
+use basket::{BasketItem, BasketPlan};
+use cache::{BiedCache, OfferSnapshot, SyncRequest};
+use callback::Callback;
+use chrono::Utc;
+use chrono_tz::Europe::Warsaw;
+use clap::Parser;
+use cli::{AccountsCommand, Cli, CliCommand};
 use db::BiedStore;
-use secrets::get_secrets;
-use std::sync::Arc;
+use filter::{OfferFilter, SortMode};
+use i18n::{escape_html, fill, Lang, Localize};
+use preferences::{Preferences, Setting};
+use secrets::{check_secrets, load_secrets, ApiConfig, DEFAULT_PROFILE};
+use spending::{Month, Spending};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::Arc,
+};
 use teloxide::{
     dispatching::{UpdateFilterExt, UpdateHandler},
     prelude::*,
-    types::{InlineKeyboardButton, InlineKeyboardMarkup, InputFile, ParseMode, Update},
-    utils::command::BotCommands,
+    types::{
+        InlineKeyboardButton, InlineKeyboardMarkup, InlineQueryResult, InlineQueryResultArticle,
+        InputFile, InputMessageContent, InputMessageContentText, ParseMode, Update, User,
+    },
+    utils::{command::BotCommands, markdown},
+    ApiError, RequestError,
 };
 use tokio::sync::Mutex;
+use validation::validate_profile;
 
 #[tokio::main]
 async fn main() {
+    let mut cli = Cli::parse();
+    pretty_env_logger::formatted_builder()
+        .filter_level(cli.log_level)
+        .init();
+
+    match cli.command.take().unwrap_or(CliCommand::Run) {
+        CliCommand::Run => run_bot(&cli, load_or_exit(&cli)).await,
+        CliCommand::CheckConfig => match check_secrets(&cli.config) {
+            Ok(problems) if problems.is_empty() => println!("{} is valid", cli.config.display()),
+            Ok(problems) => {
+                problems.iter().for_each(|p| eprintln!("{p}"));
+                std::process::exit(1);
+            }
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(1);
+            }
+        },
+        CliCommand::Accounts(command) => {
+            let profiles = match command {
+                AccountsCommand::Add { .. } | AccountsCommand::Profile { .. } => {
+                    profile_names(&load_or_exit(&cli).api_profiles)
+                }
+                _ => Vec::new(),
+            };
+            let mut store = store_or_exit(&cli);
+            match admin::accounts(&mut store, command, &profiles) {
+                Ok(out) => println!("{out}"),
+                Err(e) => {
+                    eprintln!("{e}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        CliCommand::Sync { dry_run } => {
+            let mut store = store_or_exit(&cli);
+            let Secrets {
+                api_config,
+                api_profiles,
+                cache_config,
+                ..
+            } = load_or_exit(&cli);
+            let api = api_or_exit(api_config, api_profiles);
+            match admin::sync(&mut store, &api, &cache_config, dry_run).await {
+                Ok(out) => println!("{out}"),
+                Err(e) => {
+                    eprintln!("{e}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        CliCommand::Offers(command) => {
+            let mut store = store_or_exit(&cli);
+            let Secrets {
+                api_config,
+                api_profiles,
+                cache_config,
+                ..
+            } = load_or_exit(&cli);
+            let api = api_or_exit(api_config, api_profiles);
+            match admin::offers(&mut store, &api, &cache_config, command).await {
+                Ok(out) => println!("{out}"),
+                Err(e) => {
+                    eprintln!("{e}");
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+}
+
+fn api_or_exit(api_config: ApiConfig, mut api_profiles: BTreeMap<String, ApiConfig>) -> BiedApi {
+    api_profiles.insert(DEFAULT_PROFILE.to_string(), api_config);
+    BiedApi::new(api_profiles).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        std::process::exit(1);
+    })
+}
+
+/// Brand names of the configured api profiles, keyed by profile name.
+fn brand_names(
+    api_config: &ApiConfig,
+    api_profiles: &BTreeMap<String, ApiConfig>,
+) -> HashMap<String, String> {
+    api_profiles
+        .iter()
+        .chain([(&DEFAULT_PROFILE.to_string(), api_config)])
+        .map(|(name, api)| (name.clone(), api.brand_name.clone()))
+        .collect()
+}
+
+/// Names of the configured api profiles, including the default one.
+fn profile_names(api_profiles: &BTreeMap<String, ApiConfig>) -> Vec<String> {
+    api_profiles
+        .keys()
+        .cloned()
+        .chain([DEFAULT_PROFILE.to_string()])
+        .collect()
+}
+
+fn store_or_exit(cli: &Cli) -> BiedStore {
+    BiedStore::new(&cli.db_path).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        std::process::exit(1);
+    })
+}
+
+fn load_or_exit(cli: &Cli) -> Secrets {
+    load_secrets(&cli.config).unwrap_or_else(|e| {
+        eprintln!("invalid config: {e}");
+        std::process::exit(1);
+    })
+}
+
+async fn run_bot(cli: &Cli, secrets: Secrets) {
     let Secrets {
         telegram_config,
         api_config,
+        api_profiles,
         ean_frontend,
         cdn_root,
-    } = get_secrets();
+        card_qr,
+        cache_config,
+    } = secrets;
 
     let bot = Bot::new(&telegram_config.bot_token);
-    let api = Arc::new(BiedApi::new(api_config));
-    let store = Arc::new(Mutex::new(BiedStore::new("biedstore")));
-    let cashe = Arc::new(Mutex::new(BiedCache::new()));
+    let profiles = profile_names(&api_profiles);
+    let brands = brand_names(&api_config, &api_profiles);
+    let api: Arc<dyn OfferSource> = Arc::new(api_or_exit(api_config, api_profiles));
+    let store = Arc::new(Mutex::new(store_or_exit(cli)));
+    let cashe = Arc::new(BiedCache::new(&cache_config));
 
     let cfg = ConfigParameters {
         bot_admins: telegram_config
@@ -40,16 +195,37 @@ async fn main() {
             .collect(),
         ean_frontend,
         cdn_root,
+        card_qr,
+        profiles,
+        brands,
     };
 
-    Dispatcher::builder(bot, schema())
+    tokio::spawn(notify::run(bot.clone(), store.clone()));
+    tokio::spawn(notify::sync_periodically(
+        cashe.clone(),
+        api.clone(),
+        store.clone(),
+    ));
+
+    let mut dispatcher = Dispatcher::builder(bot.clone(), schema())
         .dependencies(dptree::deps![api, store, cfg, cashe])
         .enable_ctrlc_handler()
-        .build()
-        .dispatch()
-        .await;
+        .build();
+    match telegram_config.webhook {
+        None => dispatcher.dispatch().await,
+        Some(webhook) => {
+            if let Err(e) = webhook::dispatch(&mut dispatcher, bot, webhook).await {
+                log::error!("{e}");
+                std::process::exit(1);
+            }
+        }
+    }
 }
 
+// telegram accepts at most 50 results per inline query
+const INLINE_RESULTS_LIMIT: usize = 50;
+const SEARCH_RESULTS_LIMIT: usize = 10;
+
 type HandlerResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
 
 #[derive(Clone)]
@@ -57,11 +233,16 @@ struct ConfigParameters {
     bot_admins: Vec<UserId>,
     ean_frontend: String,
     cdn_root: String,
+    card_qr: bool,
+    /// Names of the configured api profiles.
+    profiles: Vec<String>,
+    /// Brand names of the api profiles, keyed by profile name.
+    brands: HashMap<String, String>,
 }
 
 impl ConfigParameters {
     fn is_admin(&self, id: &UserId) -> bool {
-        self.bot_admins.contains(&id)
+        self.bot_admins.contains(id)
     }
 }
 
@@ -73,10 +254,34 @@ impl ConfigParameters {
 enum Command {
     #[command(description = "display this text.")]
     Help,
-    #[command(description = "list all offers.")]
-    Offers,
-    #[command(description = "synchronize offers.")]
-    Sync,
+    #[command(
+        description = "list all offers. Usage: /offers sort:discount|price|name min:30% max:10zł cat:dairy"
+    )]
+    Offers(String),
+    #[command(description = "synchronize offers. Usage: /sync [force] [title]")]
+    Sync(String),
+    #[command(description = "search all offers. Usage: /search text")]
+    Search(String),
+    #[command(
+        rename = "list_add",
+        description = "add an item to your shopping list. Usage: /list_add mleko"
+    )]
+    ListAdd(String),
+    #[command(
+        rename = "list_remove",
+        description = "remove an item from your shopping list. Usage: /list_remove number"
+    )]
+    ListRemove(String),
+    #[command(description = "show your shopping list with matching offers.")]
+    Shopping,
+    #[command(
+        description = "find the cheapest cards for a basket, your shopping list by default. Usage: /basket [mleko x2, kawa]"
+    )]
+    Basket(String),
+    #[command(description = "set the language of the replies. Usage: /language en|pl")]
+    Language(String),
+    #[command(description = "change your settings.")]
+    Settings,
 }
 
 #[derive(BotCommands, Clone)]
@@ -102,6 +307,17 @@ enum AdminCommand {
     Rename { old: String, new: String },
     #[command(description = "remove account with the specified title.")]
     Remove { title: String },
+    #[command(
+        description = "set the api profile of an account. Usage: /profile title profile",
+        parse_with = "split"
+    )]
+    Profile { title: String, profile: String },
+    #[command(description = "show the points balance of the cards. Usage: /balance [title]")]
+    Balance(String),
+    #[command(
+        description = "summarise the spending of the cards. Usage: /spending [title] [YYYY-MM]"
+    )]
+    Spending(String),
 }
 
 fn schema() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + 'static>> {
@@ -109,8 +325,15 @@ fn schema() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + 'static>>
 
     let command_handler = teloxide::filter_command::<Command, _>()
         .branch(case![Command::Help].endpoint(help))
-        .branch(case![Command::Sync].endpoint(sync))
-        .branch(case![Command::Offers].endpoint(offers));
+        .branch(case![Command::Sync(args)].endpoint(sync))
+        .branch(case![Command::Offers(args)].endpoint(offers))
+        .branch(case![Command::Search(query)].endpoint(search))
+        .branch(case![Command::ListAdd(item)].endpoint(list_add))
+        .branch(case![Command::ListRemove(item)].endpoint(list_remove))
+        .branch(case![Command::Shopping].endpoint(shopping_list))
+        .branch(case![Command::Basket(items)].endpoint(basket))
+        .branch(case![Command::Language(lang)].endpoint(language))
+        .branch(case![Command::Settings].endpoint(settings));
 
     let admin_command_handler = teloxide::filter_command::<AdminCommand, _>()
         .filter(|msg: Message, cfg: ConfigParameters| {
@@ -131,26 +354,62 @@ fn schema() -> UpdateHandler<Box<dyn std::error::Error + Send + Sync + 'static>>
         )
         .branch(case![AdminCommand::List].endpoint(list))
         .branch(case![AdminCommand::Rename { old, new }].endpoint(rename))
-        .branch(case![AdminCommand::Remove { title }].endpoint(remove));
+        .branch(case![AdminCommand::Remove { title }].endpoint(remove))
+        .branch(case![AdminCommand::Profile { title, profile }].endpoint(set_profile))
+        // card holder data and purchase history stay with the admins
+        .branch(case![AdminCommand::Balance(title)].endpoint(balance))
+        .branch(case![AdminCommand::Spending(args)].endpoint(spending));
 
     let message_handler = Update::filter_message()
         .branch(command_handler)
         .branch(admin_command_handler)
         .branch(dptree::endpoint(invalid_state));
 
+    let callback_handler = Update::filter_callback_query()
+        .filter_map_async(
+            |q: CallbackQuery, store: Arc<Mutex<BiedStore>>| async move {
+                let callback = Callback::decode(q.data.as_deref()?)?;
+                if !callback.has_account() {
+                    return Some(callback);
+                }
+                let titles = store.lock().await.account_titles();
+                callback.resolve_accounts(&titles)
+            },
+        )
+        .branch(case![Callback::ShowAccount(account)].endpoint(endpoint_button))
+        .branch(case![Callback::Offers(filter)].endpoint(offers_button))
+        .branch(case![Callback::Activate(account, id)].endpoint(activate_button))
+        .branch(case![Callback::ActivateAll(account)].endpoint(activate_all_button))
+        .branch(case![Callback::Details(account, id)].endpoint(details_button))
+        .branch(case![Callback::Setting(setting)].endpoint(settings_button));
+
     dptree::entry()
         .branch(Update::filter_message().branch(message_handler))
-        .branch(Update::filter_callback_query().endpoint(endpoint_button))
+        .branch(callback_handler)
+        .branch(Update::filter_callback_query().endpoint(outdated_button))
+        .branch(Update::filter_inline_query().endpoint(inline_query))
 }
 
-async fn help(bot: Bot, msg: Message, cfg: ConfigParameters) -> HandlerResult {
+async fn help(
+    bot: Bot,
+    msg: Message,
+    store: Arc<Mutex<BiedStore>>,
+    cfg: ConfigParameters,
+) -> HandlerResult {
+    let lang = user_lang(&store, msg.from()).await;
     bot.send_message(
         msg.chat.id,
         format!(
             "{}\n\n{}",
-            Command::descriptions().to_string(),
+            match lang.texts().help {
+                Some(help) => help.to_string(),
+                None => Command::descriptions().to_string(),
+            },
             if cfg.is_admin(&msg.from().unwrap().id) {
-                AdminCommand::descriptions().to_string()
+                match lang.texts().admin_help {
+                    Some(help) => help.to_string(),
+                    None => AdminCommand::descriptions().to_string(),
+                }
             } else {
                 "".to_string()
             }
@@ -160,15 +419,166 @@ async fn help(bot: Bot, msg: Message, cfg: ConfigParameters) -> HandlerResult {
     Ok(())
 }
 
+/// Preferences of the user and the language to reply in, which follows
+/// the user's telegram app unless picked explicitly.
+async fn user_prefs(store: &Mutex<BiedStore>, user: Option<&User>) -> (Lang, Preferences) {
+    let Some(user) = user else {
+        return Default::default();
+    };
+    let prefs = store
+        .lock()
+        .await
+        .fetch_preferences(user.id.0)
+        .unwrap_or_default();
+    let lang = prefs
+        .language
+        .unwrap_or_else(|| Lang::from_telegram(user.language_code.as_deref()));
+    (lang, prefs)
+}
+
+async fn user_lang(store: &Mutex<BiedStore>, user: Option<&User>) -> Lang {
+    user_prefs(store, user).await.0
+}
+
+async fn language(
+    bot: Bot,
+    msg: Message,
+    store: Arc<Mutex<BiedStore>>,
+    lang: String,
+) -> HandlerResult {
+    let Some(user) = msg.from() else {
+        return Ok(());
+    };
+    let text = match lang.parse::<Lang>() {
+        Ok(lang) => {
+            let mut store = store.lock().await;
+            let result = store.fetch_preferences(user.id.0).and_then(|mut prefs| {
+                prefs.language = Some(lang);
+                store.save_preferences(user.id.0, &prefs)
+            });
+            match result {
+                Ok(_) => lang.texts().language_set.to_string(),
+                Err(e) => e.localize(lang),
+            }
+        }
+        Err(_) => user_lang(&store, Some(user))
+            .await
+            .texts()
+            .language_usage
+            .to_string(),
+    };
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
+}
+
+fn render_settings(lang: Lang) -> String {
+    lang.texts().settings.to_string()
+}
+
+fn settings_keyboard(lang: Lang, prefs: &Preferences) -> InlineKeyboardMarkup {
+    let texts = lang.texts();
+    InlineKeyboardMarkup::new(Setting::ALL.map(|setting| {
+        let label = match setting {
+            Setting::Language => fill(
+                texts.setting_language,
+                &[&prefs.language.map_or(texts.language_auto, |l| l.code())],
+            ),
+            Setting::Display => fill(
+                texts.setting_display,
+                &[if prefs.compact {
+                    &texts.display_compact
+                } else {
+                    &texts.display_photos
+                }],
+            ),
+            Setting::MinDiscount => match prefs.min_discount {
+                0 => fill(texts.setting_min_discount, &[&texts.any_discount]),
+                d => fill(texts.setting_min_discount, &[&format!("-{d}%")]),
+            },
+            Setting::Digest => match prefs.digest {
+                Some(digest) => {
+                    let hour = format!("{:02}:00", digest.hour);
+                    let when = match digest.weekday {
+                        Some(day) => format!("{} {hour}", lang.weekday(day)),
+                        None => hour,
+                    };
+                    fill(texts.setting_digest, &[&when])
+                }
+                None => fill(texts.setting_digest, &[&texts.off]),
+            },
+            Setting::QuietHours => match prefs.quiet_hours {
+                Some(hours) => fill(texts.setting_quiet_hours, &[&hours]),
+                None => fill(texts.setting_quiet_hours, &[&texts.off]),
+            },
+            Setting::DefaultAccount => fill(
+                texts.setting_default_account,
+                &[&prefs
+                    .default_account
+                    .as_deref()
+                    .unwrap_or(texts.all_accounts)],
+            ),
+        };
+        callback_button(label, Callback::Setting(setting))
+    }))
+}
+
+async fn settings(bot: Bot, msg: Message, store: Arc<Mutex<BiedStore>>) -> HandlerResult {
+    let (lang, prefs) = user_prefs(&store, msg.from()).await;
+    bot.send_message(msg.chat.id, render_settings(lang))
+        .reply_markup(settings_keyboard(lang, &prefs))
+        .await?;
+    Ok(())
+}
+
+async fn settings_button(
+    bot: Bot,
+    q: CallbackQuery,
+    store: Arc<Mutex<BiedStore>>,
+    setting: Setting,
+) -> HandlerResult {
+    bot.answer_callback_query(q.id).await?;
+    let (_, mut prefs) = user_prefs(&store, Some(&q.from)).await;
+    {
+        let mut store = store.lock().await;
+        let accounts = store
+            .fetch_accounts()
+            .into_iter()
+            .map(|(title, _)| title)
+            .collect::<Vec<_>>();
+        prefs.cycle(setting, &accounts);
+        if let Err(e) = store.save_preferences(q.from.id.0, &prefs) {
+            log::warn!("couldn't save the preferences: {e}");
+        }
+    }
+    let (lang, prefs) = user_prefs(&store, Some(&q.from)).await;
+    let Some(msg) = q.message else {
+        return Ok(());
+    };
+    match bot
+        .edit_message_text(msg.chat.id, msg.id, render_settings(lang))
+        .reply_markup(settings_keyboard(lang, &prefs))
+        .await
+    {
+        Err(RequestError::Api(ApiError::MessageNotModified)) => Ok(()),
+        res => res.map(|_| ()).map_err(|e| e.into()),
+    }
+}
+
 async fn list(bot: Bot, msg: Message, store: Arc<Mutex<BiedStore>>) -> HandlerResult {
+    let texts = user_lang(&store, msg.from()).await.texts();
+    let store = store.lock().await;
     bot.send_message(
         msg.chat.id,
         store
-            .lock()
-            .await
             .fetch_accounts()
             .into_iter()
-            .map(|(title, user)| format!("*{title}* \\- {}", user))
+            .map(|(title, user)| {
+                let points = match store.fetch_summary(&title) {
+                    Ok(Some((_, summary))) => fill(texts.account_points, &[&summary.points]),
+                    _ => String::new(),
+                };
+                fill(texts.account_line, &[&title, &user, &user.profile, &points])
+            })
             .collect::<Vec<_>>()
             .join("\n\n"),
     )
@@ -184,11 +594,13 @@ async fn rename(
     store: Arc<Mutex<BiedStore>>,
     (old, new): (String, String),
 ) -> HandlerResult {
+    let lang = user_lang(&store, msg.from()).await;
+    let texts = lang.texts();
     bot.send_message(
         msg.chat.id,
         match store.lock().await.rename_account(&old, &new) {
-            Ok(_) => format!("Renamed user {} to {}", old, new),
-            Err(e) => format!("Error renaming user: {:?}", e),
+            Ok(_) => fill(texts.account_renamed, &[&old, &new]),
+            Err(e) => fill(texts.rename_failed, &[&e.localize(lang)]),
         },
     )
     .await?;
@@ -201,11 +613,13 @@ async fn remove(
     store: Arc<Mutex<BiedStore>>,
     title: String,
 ) -> HandlerResult {
+    let lang = user_lang(&store, msg.from()).await;
+    let texts = lang.texts();
     bot.send_message(
         msg.chat.id,
         match store.lock().await.remove_account(&title) {
-            Ok(u) => format!("Removed user {}", u),
-            Err(e) => format!("Error removing user: {:?}", e),
+            Ok(u) => fill(texts.account_removed, &[&u]),
+            Err(e) => fill(texts.remove_failed, &[&markdown::escape(&e.localize(lang))]),
         },
     )
     .parse_mode(ParseMode::MarkdownV2)
@@ -213,42 +627,182 @@ async fn remove(
     Ok(())
 }
 
-async fn offers(bot: Bot, msg: Message, cashe: Arc<Mutex<BiedCache>>) -> HandlerResult {
-    // TODO: don't repeat same offers
-    let offers = &cashe.lock().await.offers;
+async fn set_profile(
+    bot: Bot,
+    msg: Message,
+    store: Arc<Mutex<BiedStore>>,
+    cfg: ConfigParameters,
+    (title, profile): (String, String),
+) -> HandlerResult {
+    let lang = user_lang(&store, msg.from()).await;
+    let texts = lang.texts();
     bot.send_message(
         msg.chat.id,
-        format!(
-            "Current offers:\n\n{}",
-            offers
-                .iter()
-                .map(|e| format!(
-                    "{}:\n{}\n",
-                    e.0,
-                    e.1.iter()
-                        .map(|e| e.short_display())
-                        .collect::<Vec<_>>()
-                        .join("\n")
-                ))
-                .collect::<Vec<_>>()
-                .join("\n")
-        ),
+        match validate_profile(&profile, &cfg.profiles) {
+            Err(e) => e.localize(lang),
+            Ok(_) => match store.lock().await.set_profile(&title, &profile) {
+                Ok(_) => fill(texts.profile_set, &[&title, &profile]),
+                Err(e) => fill(texts.profile_failed, &[&e.localize(lang)]),
+            },
+        },
     )
-    .reply_markup(make_accounts_keyboard(
-        // TODO: don't clone here
-        offers.into_iter().map(|e| e.0.clone()).collect(),
-    ))
     .await?;
     Ok(())
 }
 
+async fn offers(
+    bot: Bot,
+    msg: Message,
+    store: Arc<Mutex<BiedStore>>,
+    cashe: Arc<BiedCache>,
+    cfg: ConfigParameters,
+    args: String,
+) -> HandlerResult {
+    let lang = user_lang(&store, msg.from()).await;
+    let filter = match args.parse::<OfferFilter>() {
+        Ok(f) => f,
+        Err(e) => {
+            bot.send_message(
+                msg.chat.id,
+                fill(lang.texts().offers_usage, &[&e.localize(lang)]),
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+    let cashe = cashe.snapshot();
+    bot.send_message(
+        msg.chat.id,
+        render_offers(&cashe, &filter, &cfg.brands, lang),
+    )
+    .reply_markup(make_offers_keyboard(&cashe.offers, &filter))
+    .await?;
+    Ok(())
+}
+
+/// Offers of every account, grouped by brand when accounts have different ones.
+/// `brand_names` are keyed by api profile.
+fn render_offers(
+    snapshot: &OfferSnapshot,
+    filter: &OfferFilter,
+    brand_names: &HashMap<String, String>,
+    lang: Lang,
+) -> String {
+    // TODO: don't repeat same offers
+    let mut brands = BTreeMap::<&str, Vec<_>>::new();
+    for account in &snapshot.offers {
+        let profile = snapshot
+            .profiles
+            .get(account.0)
+            .map_or(DEFAULT_PROFILE, |p| p);
+        let brand = brand_names.get(profile).map_or(profile, |b| b);
+        brands.entry(brand).or_default().push(account);
+    }
+    let single_brand = brands.len() <= 1;
+    format!(
+        "{}\n\n{}",
+        fill(lang.texts().current_offers, &[filter]),
+        brands
+            .into_iter()
+            .map(|(brand, accounts)| {
+                let accounts = render_accounts(snapshot, accounts, filter, lang);
+                if single_brand {
+                    accounts
+                } else {
+                    format!("== {brand} ==\n{accounts}")
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    )
+}
+
+fn render_accounts(
+    snapshot: &OfferSnapshot,
+    mut accounts: Vec<(&String, &Vec<Offer>)>,
+    filter: &OfferFilter,
+    lang: Lang,
+) -> String {
+    accounts.sort_by_key(|e| e.0);
+    accounts
+        .into_iter()
+        .map(|e| {
+            format!(
+                "{}{}:\n{}\n",
+                e.0,
+                snapshot
+                    .synced_at
+                    .get(e.0)
+                    .map(|t| fill(lang.texts().synced_at, &[&lang.datetime(*t)]))
+                    .unwrap_or_default(),
+                filter
+                    .apply(e.1)
+                    .iter()
+                    .map(|e| e.short_display())
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn make_offers_keyboard(
+    offers: &HashMap<String, Vec<Offer>>,
+    filter: &OfferFilter,
+) -> InlineKeyboardMarkup {
+    let sort_row = SortMode::ALL
+        .iter()
+        .filter_map(|m| {
+            callback_button(
+                if *m == filter.sort {
+                    format!("\u{2022} {m}")
+                } else {
+                    m.to_string()
+                },
+                Callback::Offers(filter.with_sort(*m)),
+            )
+        })
+        .collect::<Vec<_>>();
+    // TODO: don't clone here
+    make_accounts_keyboard(offers.keys().cloned().collect()).append_row(sort_row)
+}
+
+async fn offers_button(
+    bot: Bot,
+    q: CallbackQuery,
+    store: Arc<Mutex<BiedStore>>,
+    cashe: Arc<BiedCache>,
+    cfg: ConfigParameters,
+    filter: OfferFilter,
+) -> HandlerResult {
+    bot.answer_callback_query(q.id).await?;
+    let lang = user_lang(&store, Some(&q.from)).await;
+    let Some(msg) = q.message else {
+        return Ok(());
+    };
+    let cashe = cashe.snapshot();
+    match bot
+        .edit_message_text(
+            msg.chat.id,
+            msg.id,
+            render_offers(&cashe, &filter, &cfg.brands, lang),
+        )
+        .reply_markup(make_offers_keyboard(&cashe.offers, &filter))
+        .await
+    {
+        Err(RequestError::Api(ApiError::MessageNotModified)) => Ok(()),
+        res => res.map(|_| ()).map_err(|e| e.into()),
+    }
+}
+
 fn make_accounts_keyboard(names: Vec<String>) -> InlineKeyboardMarkup {
     InlineKeyboardMarkup::new(
         names
             .chunks(2)
             .map(|e| {
                 e.iter()
-                    .map(|n| InlineKeyboardButton::callback(n.clone(), n.clone()))
+                    .filter_map(|n| callback_button(n.clone(), Callback::ShowAccount(n.clone())))
                     .collect::<Vec<_>>()
             })
             .collect::<Vec<_>>(),
@@ -259,91 +813,794 @@ async fn endpoint_button(
     bot: Bot,
     q: CallbackQuery,
     store: Arc<Mutex<BiedStore>>,
-    cashe: Arc<Mutex<BiedCache>>,
+    cashe: Arc<BiedCache>,
     cfg: ConfigParameters,
+    title: String,
 ) -> HandlerResult {
     bot.answer_callback_query(q.id).await?;
 
-    let title = q.data.unwrap().clone();
-    let card_number = store
-        .lock()
-        .await
-        .fetch_account(&title)
-        .unwrap()
-        .card_number;
-    let mut cashe = cashe.lock().await;
-    let offers = cashe.get_offers(&title).await.unwrap();
+    let (lang, prefs) = user_prefs(&store, Some(&q.from)).await;
+    let admin = cfg.is_admin(&q.from.id);
+    let (user, activated) = {
+        let store = store.lock().await;
+        (
+            store.fetch_account(&title),
+            store.fetch_activated(&title).unwrap_or_default(),
+        )
+    };
+    // removed since the button was sent
+    let Ok(user) = user else {
+        bot.send_message(q.from.id, lang.texts().outdated_button)
+            .await?;
+        return Ok(());
+    };
+    let cashe = cashe.snapshot();
+    let Some(offers) = cashe.get_offers(&title) else {
+        bot.send_message(q.from.id, lang.texts().no_account_offers)
+            .await?;
+        return Ok(());
+    };
 
     for o in offers {
-        let Offer {
-            name,
-            details,
-            limit,
-            image,
-            regular_price,
-            regular_price_unit,
-            offer_price,
-            offer_price_unit,
-            ..
-        } = o;
-        let text = format!("<b>{name}</b>\n<code>{details}</code>\n{limit}\n{regular_price} -> {offer_price}\n{regular_price_unit} -> {offer_price_unit}");
-        match image {
-            Some(img) => {
-                let pic = reqwest::get(format!("{}{}", cfg.cdn_root, img))
-                    .await?
-                    .bytes()
-                    .await?;
-                bot.send_photo(q.from.id, InputFile::memory(pic))
-                    .caption(text)
-                    .parse_mode(ParseMode::Html)
-                    .await?;
+        let keyboard = offer_keyboard(&title, o, activated.contains(o.id()), admin, lang);
+        send_offer(
+            &bot,
+            q.from.id,
+            o,
+            o.html_display(),
+            keyboard,
+            &cfg,
+            prefs.compact,
+        )
+        .await?;
+    }
+    send_card(&bot, q.from.id, &user.card_number, &cfg, lang).await?;
+    let active = offers.iter().filter(|o| activated.contains(o.id())).count();
+    let mut summary = bot.send_message(q.from.id, activation_summary(active, offers.len(), lang));
+    if admin && active < offers.len() {
+        summary = summary.reply_markup(activate_all_keyboard(&title, lang));
+    }
+    summary.await?;
+    Ok(())
+}
+
+async fn send_offer(
+    bot: &Bot,
+    chat: UserId,
+    o: &Offer,
+    text: String,
+    keyboard: Option<InlineKeyboardMarkup>,
+    cfg: &ConfigParameters,
+    compact: bool,
+) -> HandlerResult {
+    match o.image.as_ref().filter(|_| !compact) {
+        Some(img) => {
+            let pic = reqwest::get(format!("{}{}", cfg.cdn_root, img))
+                .await?
+                .bytes()
+                .await?;
+            let mut rq = bot
+                .send_photo(chat, InputFile::memory(pic))
+                .caption(text)
+                .parse_mode(ParseMode::Html);
+            if let Some(keyboard) = keyboard {
+                rq = rq.reply_markup(keyboard);
             }
-            None => {
-                bot.send_message(q.from.id, text)
-                    .parse_mode(ParseMode::Html)
-                    .await?;
+            rq.await?;
+        }
+        None => {
+            let mut rq = bot.send_message(chat, text).parse_mode(ParseMode::Html);
+            if let Some(keyboard) = keyboard {
+                rq = rq.reply_markup(keyboard);
             }
+            rq.await?;
         }
     }
-    bot.send_message(
+    Ok(())
+}
+
+/// Everything known about the offer, opened with the details button.
+fn offer_details(o: &Offer, lang: Lang) -> String {
+    let texts = lang.texts();
+    let mut lines = vec![
+        format!("<b>{}</b>", escape_html(&o.name)),
+        escape_html(&o.details.replace(';', "\n")),
+    ];
+    if !o.tags.is_empty() {
+        lines.push(format!(
+            "<i>{}</i>",
+            escape_html(&o.tags.join(" \u{2022} "))
+        ));
+    }
+    if !o.offer_type.is_empty() {
+        lines.push(fill(texts.offer_type, &[&escape_html(&o.offer_type)]));
+    }
+    lines.push(fill(texts.offer_valid, &[&escape_html(&o.human_time)]));
+    if !o.limit.is_empty() {
+        lines.push(fill(texts.offer_limit, &[&escape_html(&o.limit)]));
+    }
+    lines.push(format!(
+        "{} -> <b>{}</b> (-{}%)",
+        escape_html(&o.regular_price),
+        escape_html(&o.offer_price),
+        o.discount_percent
+    ));
+    lines.push(escape_html(&format!(
+        "{} -> {}",
+        o.regular_price_unit, o.offer_price_unit
+    )));
+    if let Some(url) = &o.product_url {
+        lines.push(format!(
+            "<a href=\"{}\">{}</a>",
+            escape_html(url),
+            texts.product_page
+        ));
+    }
+    lines.join("\n")
+}
+
+/// A button for the callback, `None` if its data doesn't fit.
+fn callback_button(text: impl Into<String>, callback: Callback) -> Option<InlineKeyboardButton> {
+    Some(InlineKeyboardButton::callback(text, callback.encode()?))
+}
+
+/// Only admins get the activate button, as it acts on the family accounts.
+/// Offers with ids too long for the callback data get no buttons.
+fn offer_keyboard(
+    account: &str,
+    o: &Offer,
+    active: bool,
+    admin: bool,
+    lang: Lang,
+) -> Option<InlineKeyboardMarkup> {
+    let activate = callback_button(
+        if active {
+            lang.texts().active
+        } else {
+            lang.texts().activate
+        },
+        Callback::Activate(account.to_string(), o.id().to_string()),
+    )
+    .filter(|_| admin);
+    let details = callback_button(
+        lang.texts().details,
+        Callback::Details(account.to_string(), o.id().to_string()),
+    );
+    let row = activate.into_iter().chain(details).collect::<Vec<_>>();
+    (!row.is_empty()).then(|| InlineKeyboardMarkup::new([row]))
+}
+
+fn activate_all_keyboard(account: &str, lang: Lang) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new([callback_button(
+        lang.texts().activate_all,
+        Callback::ActivateAll(account.to_string()),
+    )])
+}
+
+fn activation_summary(active: usize, total: usize, lang: Lang) -> String {
+    fill(lang.texts().activation_summary, &[&active, &total])
+}
+
+/// Activates the offers that aren't active yet, returning the ids active afterwards
+/// and the errors of the ones that failed.
+async fn activate_offers(
+    store: &Mutex<BiedStore>,
+    api: &dyn OfferSource,
+    title: &str,
+    offers: &[Offer],
+    ids: &[&str],
+) -> Result<(HashSet<String>, Vec<api::ApiError>), db::StoreError> {
+    let (mut user, active) = {
+        let store = store.lock().await;
+        (store.fetch_account(title)?, store.fetch_activated(title)?)
+    };
+
+    let mut errors = Vec::new();
+    let mut activated = Vec::new();
+    let pending = ids
+        .iter()
+        .filter(|id| !active.contains(**id))
+        .collect::<Vec<_>>();
+    for id in pending {
+        match api.activate_offer(&user.profile, &mut user.auth, id).await {
+            Ok(()) => activated.push(id.to_string()),
+            Err(e) => errors.push(e),
+        }
+    }
+
+    // other activations may have finished while the lock was released
+    let mut store = store.lock().await;
+    let mut active = store.fetch_activated(title)?;
+    active.extend(activated);
+    // offers change every day, so only the current ones are kept
+    active.retain(|id| offers.iter().any(|o| o.id() == id));
+    store.save_activated(title, &active)?;
+    store.save_cookies(&[(title.to_string(), user.auth.cookies)])?;
+    Ok((active, errors))
+}
+
+async fn activate_button(
+    bot: Bot,
+    q: CallbackQuery,
+    store: Arc<Mutex<BiedStore>>,
+    api: Arc<dyn OfferSource>,
+    cashe: Arc<BiedCache>,
+    cfg: ConfigParameters,
+    (account, id): (String, String),
+) -> HandlerResult {
+    let lang = user_lang(&store, Some(&q.from)).await;
+    let texts = lang.texts();
+    if !cfg.is_admin(&q.from.id) {
+        bot.answer_callback_query(q.id)
+            .text(texts.admins_only)
+            .await?;
+        return Ok(());
+    }
+    let cashe = cashe.snapshot();
+    let Some((offers, o)) = cashe
+        .get_offers(&account)
+        .and_then(|offers| Some((offers, offers.iter().find(|o| o.id() == id)?)))
+    else {
+        bot.answer_callback_query(q.id)
+            .text(texts.offer_unavailable)
+            .await?;
+        return Ok(());
+    };
+    let text = match activate_offers(&store, api.as_ref(), &account, offers, &[&id]).await {
+        Ok((_, errors)) if !errors.is_empty() => {
+            fill(texts.activation_failed, &[&errors[0].localize(lang)])
+        }
+        Ok(_) => {
+            if let (Some(msg), Some(keyboard)) =
+                (q.message, offer_keyboard(&account, o, true, true, lang))
+            {
+                match bot
+                    .edit_message_reply_markup(msg.chat.id, msg.id)
+                    .reply_markup(keyboard)
+                    .await
+                {
+                    Err(RequestError::Api(ApiError::MessageNotModified)) | Ok(_) => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            texts.offer_activated.to_string()
+        }
+        Err(e) => fill(texts.activation_failed, &[&e.localize(lang)]),
+    };
+    bot.answer_callback_query(q.id).text(text).await?;
+    Ok(())
+}
+
+async fn details_button(
+    bot: Bot,
+    q: CallbackQuery,
+    store: Arc<Mutex<BiedStore>>,
+    cashe: Arc<BiedCache>,
+    cfg: ConfigParameters,
+    (account, id): (String, String),
+) -> HandlerResult {
+    let cashe = cashe.snapshot();
+    // gone with a sync since the button was sent
+    let Some(o) = cashe
+        .get_offers(&account)
+        .and_then(|offers| offers.iter().find(|o| o.id() == id))
+    else {
+        return outdated_button(bot, q, store).await;
+    };
+    bot.answer_callback_query(q.id).await?;
+    let lang = user_lang(&store, Some(&q.from)).await;
+    // the details always come with the full size image
+    send_offer(
+        &bot,
         q.from.id,
-        format!("[View card\u{1F4B3}]({}{})", cfg.ean_frontend, card_number),
+        o,
+        offer_details(o, lang),
+        None,
+        &cfg,
+        false,
     )
-    .parse_mode(ParseMode::MarkdownV2)
+    .await
+}
+
+async fn activate_all_button(
+    bot: Bot,
+    q: CallbackQuery,
+    store: Arc<Mutex<BiedStore>>,
+    api: Arc<dyn OfferSource>,
+    cashe: Arc<BiedCache>,
+    cfg: ConfigParameters,
+    account: String,
+) -> HandlerResult {
+    let lang = user_lang(&store, Some(&q.from)).await;
+    if !cfg.is_admin(&q.from.id) {
+        bot.answer_callback_query(q.id)
+            .text(lang.texts().admins_only)
+            .await?;
+        return Ok(());
+    }
+    bot.answer_callback_query(q.id).await?;
+    let cashe = cashe.snapshot();
+    let Some(offers) = cashe.get_offers(&account) else {
+        bot.send_message(q.from.id, lang.texts().no_account_offers)
+            .await?;
+        return Ok(());
+    };
+    let ids = offers.iter().map(|o| o.id()).collect::<Vec<_>>();
+    let text = match activate_offers(&store, api.as_ref(), &account, offers, &ids).await {
+        Ok((active, errors)) => {
+            let mut text = activation_summary(active.len(), offers.len(), lang);
+            if let Some(e) = errors.first() {
+                text.push('\n');
+                text.push_str(&fill(
+                    lang.texts().failed_count,
+                    &[&errors.len(), &e.localize(lang)],
+                ));
+            }
+            text
+        }
+        Err(e) => fill(lang.texts().activations_failed, &[&e.localize(lang)]),
+    };
+    match q.message {
+        Some(msg) => match bot.edit_message_text(msg.chat.id, msg.id, text).await {
+            Err(RequestError::Api(ApiError::MessageNotModified)) | Ok(_) => {}
+            Err(e) => return Err(e.into()),
+        },
+        None => {
+            bot.send_message(q.from.id, text).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Buttons of older versions, or of accounts and offers that are gone.
+async fn outdated_button(
+    bot: Bot,
+    q: CallbackQuery,
+    store: Arc<Mutex<BiedStore>>,
+) -> HandlerResult {
+    let lang = user_lang(&store, Some(&q.from)).await;
+    bot.answer_callback_query(q.id)
+        .text(lang.texts().outdated_button)
+        .show_alert(true)
+        .await?;
+    Ok(())
+}
+
+async fn search(
+    bot: Bot,
+    msg: Message,
+    store: Arc<Mutex<BiedStore>>,
+    cashe: Arc<BiedCache>,
+    query: String,
+) -> HandlerResult {
+    let texts = user_lang(&store, msg.from()).await.texts();
+    if query.trim().is_empty() {
+        bot.send_message(msg.chat.id, texts.search_usage).await?;
+        return Ok(());
+    }
+    let cashe = cashe.snapshot();
+    let mut seen = HashSet::new();
+    let hits = cashe
+        .search(&query)
+        .into_iter()
+        .filter(|(_, o)| seen.insert((&o.name, &o.offer_price)))
+        .take(SEARCH_RESULTS_LIMIT)
+        .collect::<Vec<_>>();
+    if hits.is_empty() {
+        bot.send_message(msg.chat.id, fill(texts.no_search_results, &[&query]))
+            .await?;
+        return Ok(());
+    }
+    bot.send_message(
+        msg.chat.id,
+        hits.iter()
+            .enumerate()
+            .map(|(i, (account, o))| {
+                format!(
+                    "{}. {} (-{}%, {account})",
+                    i + 1,
+                    o.short_display(),
+                    o.discount_percent
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+    )
+    .reply_markup(InlineKeyboardMarkup::new(
+        hits.iter()
+            .enumerate()
+            .filter_map(|(i, (account, o))| {
+                callback_button(
+                    (i + 1).to_string(),
+                    Callback::Details(account.to_string(), o.id().to_string()),
+                )
+            })
+            .collect::<Vec<_>>()
+            .chunks(5)
+            .map(|e| e.to_vec())
+            .collect::<Vec<_>>(),
+    ))
     .await?;
     Ok(())
 }
 
+async fn send_card(
+    bot: &Bot,
+    chat: UserId,
+    card_number: &str,
+    cfg: &ConfigParameters,
+    lang: Lang,
+) -> HandlerResult {
+    let link = format!(
+        "[{}\u{1F4B3}]({}{})",
+        lang.texts().view_card,
+        cfg.ean_frontend,
+        card_number
+    );
+    match barcode::ean13_png(card_number) {
+        Ok(png) => {
+            bot.send_photo(chat, InputFile::memory(png))
+                .caption(format!("`{card_number}`\n{link}"))
+                .parse_mode(ParseMode::MarkdownV2)
+                .await?;
+        }
+        // cards that are not valid EAN-13 can still be shown by the frontend
+        Err(_) => {
+            bot.send_message(chat, link)
+                .parse_mode(ParseMode::MarkdownV2)
+                .await?;
+        }
+    }
+    if cfg.card_qr {
+        bot.send_photo(chat, InputFile::memory(barcode::qr_png(card_number)?))
+            .await?;
+    }
+    Ok(())
+}
+
+async fn inline_query(
+    bot: Bot,
+    q: InlineQuery,
+    cashe: Arc<BiedCache>,
+    cfg: ConfigParameters,
+) -> HandlerResult {
+    let cashe = cashe.snapshot();
+    let mut seen = HashSet::new();
+    let results = cashe
+        .search(&q.query)
+        .into_iter()
+        .filter(|(_, o)| seen.insert((&o.name, &o.offer_price)))
+        .take(INLINE_RESULTS_LIMIT)
+        .enumerate()
+        .map(|(i, (_, o))| offer_article(i.to_string(), o, &cfg))
+        .collect::<Vec<_>>();
+    bot.answer_inline_query(q.id, results)
+        .is_personal(true)
+        .await?;
+    Ok(())
+}
+
+fn offer_article(id: String, o: &Offer, cfg: &ConfigParameters) -> InlineQueryResult {
+    let article = InlineQueryResultArticle::new(
+        id,
+        o.name.clone(),
+        InputMessageContent::Text(
+            InputMessageContentText::new(o.html_display()).parse_mode(ParseMode::Html),
+        ),
+    )
+    .description(format!(
+        "{} -> {} (-{}%)",
+        o.regular_price, o.offer_price, o.discount_percent
+    ));
+    let thumb = o
+        .thumb_url
+        .as_ref()
+        .and_then(|img| reqwest::Url::parse(&format!("{}{}", cfg.cdn_root, img)).ok());
+    InlineQueryResult::Article(match thumb {
+        Some(url) => article.thumb_url(url),
+        None => article,
+    })
+}
+
 async fn sync(
     bot: Bot,
     msg: Message,
-    cashe: Arc<Mutex<BiedCache>>,
-    api: Arc<BiedApi>,
+    cashe: Arc<BiedCache>,
+    api: Arc<dyn OfferSource>,
     store: Arc<Mutex<BiedStore>>,
+    args: String,
 ) -> HandlerResult {
-    let mut store = store.lock().await;
-    let mut cashe = cashe.lock().await;
-    match cashe.sync_offers(&mut store, &api).await {
-        Ok(_) => {
-            bot.send_message(msg.chat.id, "Synching finished.").await?;
-        }
-        Err(e) => {
-            bot.send_message(msg.chat.id, format!("Synching failed: {:?}", e))
+    let lang = user_lang(&store, msg.from()).await;
+    let request = SyncRequest::parse(&args);
+    if let Some(title) = &request.only {
+        let accounts = store.lock().await.fetch_accounts();
+        if !accounts.iter().any(|(name, _)| name == title) {
+            bot.send_message(msg.chat.id, fill(lang.texts().no_account, &[title]))
                 .await?;
+            return Ok(());
         }
     }
+    let report = notify::sync_offers(&cashe, api.as_ref(), &store, request).await;
+    bot.send_message(msg.chat.id, report.render(lang)).await?;
     Ok(())
 }
 
-async fn invalid_state(bot: Bot, msg: Message) -> HandlerResult {
+/// The account named in a command, otherwise the user's default one or all of them.
+fn pick_accounts(
+    accounts: Vec<(String, AuthenticatedUser)>,
+    title: Option<&str>,
+    prefs: &Preferences,
+) -> Vec<(String, AuthenticatedUser)> {
+    let titles = accounts.iter().map(|(t, _)| t.clone()).collect::<Vec<_>>();
+    match title.or(prefs.default_account(&titles)) {
+        Some(title) => accounts.into_iter().filter(|(t, _)| t == title).collect(),
+        None => accounts,
+    }
+}
+
+async fn balance(
+    bot: Bot,
+    msg: Message,
+    store: Arc<Mutex<BiedStore>>,
+    api: Arc<dyn OfferSource>,
+    title: String,
+) -> HandlerResult {
+    let (lang, prefs) = user_prefs(&store, msg.from()).await;
+    let title = title.trim();
+    let accounts = pick_accounts(
+        store.lock().await.fetch_accounts(),
+        Some(title).filter(|t| !t.is_empty()),
+        &prefs,
+    );
+    if accounts.is_empty() {
+        bot.send_message(msg.chat.id, fill(lang.texts().no_account, &[&title]))
+            .await?;
+        return Ok(());
+    }
+
+    let mut lines = Vec::new();
+    for (name, mut user) in accounts {
+        let result = api.get_account_summary(&user.profile, &mut user.auth).await;
+        let mut store = store.lock().await;
+        if let Err(e) = store.save_cookies(&[(name.clone(), user.auth.cookies)]) {
+            log::warn!("couldn't save cookies: {e}");
+        }
+        lines.push(match result {
+            Ok(summary) => {
+                if let Err(e) = store.save_summary(&name, &summary, Utc::now()) {
+                    log::warn!("couldn't save the summary of {name}: {e}");
+                }
+                format!("{name}: {}", summary.render(lang))
+            }
+            // fall back to the last known balance
+            Err(e) => match store.fetch_summary(&name) {
+                Ok(Some((fetched_at, summary))) => format!(
+                    "{name}: {}",
+                    fill(
+                        lang.texts().summary_as_of,
+                        &[
+                            &summary.render(lang),
+                            &lang.datetime(fetched_at),
+                            &e.localize(lang)
+                        ]
+                    )
+                ),
+                _ => format!("{name}: {}", e.localize(lang)),
+            },
+        });
+    }
+    bot.send_message(msg.chat.id, lines.join("\n")).await?;
+    Ok(())
+}
+
+async fn spending(
+    bot: Bot,
+    msg: Message,
+    store: Arc<Mutex<BiedStore>>,
+    api: Arc<dyn OfferSource>,
+    args: String,
+) -> HandlerResult {
+    let (lang, prefs) = user_prefs(&store, msg.from()).await;
+    let texts = lang.texts();
+    let mut title = None;
+    let mut month = Month::of(Utc::now().with_timezone(&Warsaw).date_naive());
+    for arg in args.split_whitespace() {
+        match Month::parse_arg(arg) {
+            Some(Ok(m)) => month = m,
+            Some(Err(e)) => {
+                bot.send_message(msg.chat.id, e.localize(lang)).await?;
+                return Ok(());
+            }
+            None => title = Some(arg),
+        }
+    }
+    let accounts = pick_accounts(store.lock().await.fetch_accounts(), title, &prefs);
+    if accounts.is_empty() {
+        bot.send_message(
+            msg.chat.id,
+            fill(texts.no_account, &[&title.unwrap_or_default()]),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let mut lines = vec![fill(texts.spending_in, &[&lang.month(month)])];
+    let mut household = Vec::new();
+    for (name, mut user) in accounts {
+        // only fetch what's newer than the stored history
+        let since = store
+            .lock()
+            .await
+            .fetch_transactions(&name)
+            .ok()
+            .and_then(|h| h.last().map(|t| t.date));
+        let fetched = api
+            .get_transactions(&user.profile, &mut user.auth, since)
+            .await;
+        let mut store = store.lock().await;
+        if let Err(e) = store.save_cookies(&[(name.clone(), user.auth.cookies)]) {
+            log::warn!("couldn't save cookies: {e}");
+        }
+        let note = match fetched.map(|t| store.add_transactions(&name, t)) {
+            Ok(Ok(_)) => String::new(),
+            Ok(Err(e)) => fill(texts.history_not_saved, &[&e.localize(lang)]),
+            Err(e) => fill(texts.history_not_updated, &[&e.localize(lang)]),
+        };
+        let history = store.fetch_transactions(&name).unwrap_or_default();
+        lines.push(format!(
+            "{name}: {}{note}",
+            Spending::of(&history, month).render(lang)
+        ));
+        household.extend(history);
+    }
+    if lines.len() > 2 {
+        lines.push(fill(
+            texts.together,
+            &[&Spending::of(&household, month).render(lang)],
+        ));
+    }
+    bot.send_message(msg.chat.id, lines.join("\n")).await?;
+    Ok(())
+}
+
+async fn list_add(
+    bot: Bot,
+    msg: Message,
+    store: Arc<Mutex<BiedStore>>,
+    item: String,
+) -> HandlerResult {
+    let item = item.trim();
+    let Some(user) = msg.from() else {
+        return Ok(());
+    };
+    let lang = user_lang(&store, Some(user)).await;
+    let texts = lang.texts();
+    if item.is_empty() {
+        bot.send_message(msg.chat.id, texts.list_add_usage).await?;
+        return Ok(());
+    }
+    let mut store = store.lock().await;
+    let result = store.fetch_shopping_list(user.id.0).and_then(|mut items| {
+        items.push(item.to_string());
+        store.save_shopping_list(user.id.0, &items)
+    });
+    // the alerts about the list aren't replies, so they need the language noted
+    let app_language = Some(Lang::from_telegram(user.language_code.as_deref()));
+    let noted = store.fetch_preferences(user.id.0).and_then(|mut prefs| {
+        if prefs.app_language == app_language {
+            return Ok(());
+        }
+        prefs.app_language = app_language;
+        store.save_preferences(user.id.0, &prefs)
+    });
+    if let Err(e) = noted {
+        log::warn!("couldn't note the language of {}: {e}", user.id);
+    }
+    bot.send_message(
+        msg.chat.id,
+        match result {
+            Ok(_) => fill(texts.list_added, &[&item]),
+            Err(e) => fill(texts.list_add_failed, &[&e.localize(lang)]),
+        },
+    )
+    .await?;
+    Ok(())
+}
+
+async fn list_remove(
+    bot: Bot,
+    msg: Message,
+    store: Arc<Mutex<BiedStore>>,
+    item: String,
+) -> HandlerResult {
+    let Some(user) = msg.from() else {
+        return Ok(());
+    };
+    let lang = user_lang(&store, Some(user)).await;
+    let texts = lang.texts();
+    let mut store = store.lock().await;
+    let mut items = store.fetch_shopping_list(user.id.0).unwrap_or_default();
+    let text = match item.trim().parse::<usize>() {
+        Ok(i) if (1..=items.len()).contains(&i) => {
+            let removed = items.remove(i - 1);
+            match store.save_shopping_list(user.id.0, &items) {
+                Ok(_) => fill(texts.list_removed, &[&removed]),
+                Err(e) => fill(texts.list_remove_failed, &[&e.localize(lang)]),
+            }
+        }
+        _ => texts.list_remove_usage.to_string(),
+    };
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
+}
+
+async fn shopping_list(
+    bot: Bot,
+    msg: Message,
+    store: Arc<Mutex<BiedStore>>,
+    cashe: Arc<BiedCache>,
+) -> HandlerResult {
+    let Some(user) = msg.from() else {
+        return Ok(());
+    };
+    let lang = user_lang(&store, Some(user)).await;
+    let items = store
+        .lock()
+        .await
+        .fetch_shopping_list(user.id.0)
+        .unwrap_or_default();
     bot.send_message(
         msg.chat.id,
-        "Unable to handle the message. Type /help to see the usage.",
+        shopping::annotate(&items, &cashe.snapshot(), lang),
     )
     .await?;
     Ok(())
 }
 
+async fn basket(
+    bot: Bot,
+    msg: Message,
+    store: Arc<Mutex<BiedStore>>,
+    cashe: Arc<BiedCache>,
+    items: String,
+) -> HandlerResult {
+    let lang = user_lang(&store, msg.from()).await;
+    let items = if items.trim().is_empty() {
+        match msg.from() {
+            Some(user) => store
+                .lock()
+                .await
+                .fetch_shopping_list(user.id.0)
+                .unwrap_or_default(),
+            None => Vec::new(),
+        }
+    } else {
+        items.split(',').map(|e| e.to_string()).collect()
+    };
+    if items.is_empty() {
+        bot.send_message(msg.chat.id, lang.texts().basket_usage)
+            .await?;
+        return Ok(());
+    }
+    let text = match items
+        .iter()
+        .map(|e| e.parse::<BasketItem>())
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(items) => BasketPlan::new(&items, &cashe.snapshot()).render(lang),
+        Err(e) => fill(lang.texts().basket_error, &[&e.localize(lang)]),
+    };
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
+}
+
+async fn invalid_state(bot: Bot, msg: Message, store: Arc<Mutex<BiedStore>>) -> HandlerResult {
+    let lang = user_lang(&store, msg.from()).await;
+    bot.send_message(msg.chat.id, lang.texts().unknown_message)
+        .await?;
+    Ok(())
+}
+
 async fn add_acconut(
     bot: Bot,
     msg: Message,
@@ -357,6 +1614,8 @@ async fn add_acconut(
         String,
     ),
 ) -> HandlerResult {
+    let lang = user_lang(&store, msg.from()).await;
+    let texts = lang.texts();
     let mut store = store.lock().await;
     bot.send_message(
         msg.chat.id,
@@ -369,13 +1628,122 @@ async fn add_acconut(
                     users1,
                     users2,
                     csrf_token,
+                    cookies: Default::default(),
                 },
+                profile: DEFAULT_PROFILE.to_string(),
             },
         ) {
-            Ok(_) => "Account added succesfully".to_string(),
-            Err(e) => format!("Error adding account: {:?}", e),
+            Ok(_) => texts.account_added.to_string(),
+            Err(e) => fill(texts.add_failed, &[&e.localize(lang)]),
         },
     )
     .await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fake::{FakeResponse, FakeSource};
+
+    fn help_commands(help: &str) -> Vec<&str> {
+        help.lines()
+            .filter_map(|line| line.split_whitespace().next())
+            .filter(|word| word.starts_with('/'))
+            .collect()
+    }
+
+    #[test]
+    fn translated_help_lists_every_command() {
+        for lang in [Lang::En, Lang::Pl] {
+            let texts = lang.texts();
+            for (help, commands) in [
+                (texts.help, Command::bot_commands()),
+                (texts.admin_help, AdminCommand::bot_commands()),
+            ] {
+                if let Some(help) = help {
+                    let names = commands
+                        .iter()
+                        .map(|c| c.command.as_str())
+                        .collect::<Vec<_>>();
+                    assert_eq!(help_commands(help), names);
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn activates_only_the_inactive_offers() {
+        let store = Mutex::new(BiedStore::temporary());
+        {
+            let mut store = store.lock().await;
+            store.insert_account("jan", fake::account("jan")).unwrap();
+            let active = HashSet::from(["1001".to_string(), "expired".to_string()]);
+            store.save_activated("jan", &active).unwrap();
+        }
+        let api = FakeSource::default()
+            .with("jan", FakeResponse::Offers(String::new()))
+            .rejecting("1003");
+        let offers = api::parse_offers(fake::OFFERS_FIXTURE).unwrap();
+
+        let ids = ["1001", "1002", "1003"];
+        let (active, errors) = activate_offers(&store, &api, "jan", &offers, &ids)
+            .await
+            .unwrap();
+        // 1001 was active already
+        assert_eq!(api.calls(), 2);
+        assert!(matches!(errors[..], [api::ApiError::Decode(_)]));
+        assert_eq!(
+            active,
+            HashSet::from(["1001".to_string(), "1002".to_string()])
+        );
+
+        let store = store.lock().await;
+        assert_eq!(store.fetch_activated("jan").unwrap(), active);
+        let user = store.fetch_account("jan").unwrap();
+        assert_eq!(
+            user.auth.cookies.iter().collect::<Vec<_>>(),
+            [("session", "1002")]
+        );
+    }
+
+    #[tokio::test]
+    async fn groups_offers_by_brand() {
+        let api = FakeSource::default()
+            .with(
+                "jan",
+                FakeResponse::Offers(fake::OFFERS_FIXTURE.to_string()),
+            )
+            .with(
+                "anna",
+                FakeResponse::Offers(fake::OFFERS_FIXTURE.to_string()),
+            );
+        let mut anna = fake::account("anna");
+        anna.profile = "other".to_string();
+        let cache = BiedCache::new(&Default::default());
+        cache
+            .sync_offers(
+                vec![
+                    ("jan".to_string(), fake::account("jan")),
+                    ("anna".to_string(), anna),
+                ],
+                &api,
+                SyncRequest::default(),
+            )
+            .await;
+        let brands = HashMap::from([
+            (DEFAULT_PROFILE.to_string(), "Biedronka".to_string()),
+            ("other".to_string(), "Hebe".to_string()),
+        ]);
+
+        let text = render_offers(
+            &cache.snapshot(),
+            &OfferFilter::default(),
+            &brands,
+            Lang::En,
+        );
+        let biedronka = text.find("== Biedronka ==\njan").unwrap();
+        let hebe = text.find("== Hebe ==\nanna").unwrap();
+        assert!(biedronka < hebe);
+    }
+}
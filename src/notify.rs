@@ -0,0 +1,354 @@
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use chrono::{DateTime, Timelike, Utc};
+use chrono_tz::Europe::Warsaw;
+use serde::{Deserialize, Serialize};
+use teloxide::{prelude::*, types::UserId, ApiError, RequestError};
+use tokio::sync::Mutex;
+
+use crate::{
+    api::OfferSource,
+    cache::{BiedCache, OfferSnapshot, SyncReport, SyncRequest},
+    db::{BiedStore, StoreError},
+    i18n::{fill, Lang},
+    preferences::Preferences,
+    shopping,
+};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// Only the outdated accounts are fetched, so this mostly finds nothing to do.
+const SYNC_INTERVAL: Duration = Duration::from_secs(15 * 60);
+/// How long delivered ids are remembered so they aren't queued again.
+const KEEP_DELIVERED_DAYS: i64 = 14;
+/// Telegram rejects longer messages.
+const MAX_MESSAGE_LEN: usize = 4096;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Notification {
+    /// Notifications with the same id are only delivered once.
+    pub id: String,
+    pub text: String,
+    pub queued_at: i64,
+}
+
+/// Notifications of a user waiting for delivery.
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Outbox {
+    pub pending: Vec<Notification>,
+    /// Delivered ids and when they were delivered.
+    delivered: BTreeMap<String, i64>,
+}
+
+impl Outbox {
+    /// Queues the notification unless it's already pending or was delivered.
+    pub fn push(&mut self, notification: Notification) {
+        if !self.delivered.contains_key(&notification.id)
+            && !self.pending.iter().any(|n| n.id == notification.id)
+        {
+            self.pending.push(notification);
+        }
+    }
+
+    /// Removes the notifications from the queue and remembers them as delivered.
+    pub fn mark_delivered(&mut self, ids: &[String], now: DateTime<Utc>) {
+        self.pending.retain(|n| !ids.contains(&n.id));
+        self.delivered
+            .extend(ids.iter().map(|id| (id.clone(), now.timestamp())));
+        let cutoff = now.timestamp() - KEEP_DELIVERED_DAYS * 24 * 3600;
+        self.delivered.retain(|_, t| *t >= cutoff);
+    }
+
+    /// Whether the pending notifications should be sent now. Without a digest
+    /// they go out right away, otherwise once the digest time has passed,
+    /// and never during quiet hours.
+    pub fn is_due(&self, prefs: &Preferences, now: DateTime<Utc>) -> bool {
+        let hour = now.with_timezone(&Warsaw).hour();
+        if prefs.quiet_hours.is_some_and(|q| q.contains(hour)) {
+            return false;
+        }
+        match prefs.digest {
+            None => !self.pending.is_empty(),
+            Some(digest) => digest
+                .last_slot(now)
+                .is_some_and(|slot| self.pending.iter().any(|n| n.queued_at <= slot.timestamp())),
+        }
+    }
+}
+
+/// Queues the offers matching the shopping lists for their owners.
+pub fn queue_offer_alerts(
+    store: &mut BiedStore,
+    snapshot: &OfferSnapshot,
+    now: DateTime<Utc>,
+) -> Result<(), StoreError> {
+    for (user, items) in store.fetch_shopping_lists()? {
+        let prefs = store.fetch_preferences(user)?;
+        let lang = prefs.notification_lang();
+        let mut outbox = store.fetch_outbox(user)?;
+        for item in &items {
+            for (offer, mut accounts) in shopping::matching_offers(item, snapshot) {
+                if offer.discount_percent < prefs.min_discount {
+                    continue;
+                }
+                accounts.sort();
+                outbox.push(Notification {
+                    id: offer.id().to_string(),
+                    text: fill(
+                        lang.texts().offer_alert,
+                        &[
+                            item,
+                            &offer.short_display(),
+                            &offer.discount_percent,
+                            &accounts.join(", "),
+                        ],
+                    ),
+                    queued_at: now.timestamp(),
+                });
+            }
+        }
+        store.save_outbox(user, &outbox)?;
+    }
+    Ok(())
+}
+
+/// Syncs the offers, saves the cookies the api rotated and queues the alerts
+/// about the new offers, as everyone hears what they mean for their shopping list.
+pub async fn sync_offers(
+    cache: &BiedCache,
+    api: &dyn OfferSource,
+    store: &Mutex<BiedStore>,
+    request: SyncRequest,
+) -> SyncReport {
+    let accounts = store.lock().await.fetch_accounts();
+    let report = cache.sync_offers(accounts, api, request).await;
+    let mut store = store.lock().await;
+    if let Err(e) = store.save_cookies(&report.cookies) {
+        log::warn!("couldn't save cookies: {e}");
+    }
+    if let Err(e) = queue_offer_alerts(&mut store, &cache.snapshot(), Utc::now()) {
+        log::warn!("couldn't queue offer alerts: {e}");
+    }
+    report
+}
+
+/// Refreshes the outdated offers until the bot stops.
+pub async fn sync_periodically(
+    cache: Arc<BiedCache>,
+    api: Arc<dyn OfferSource>,
+    store: Arc<Mutex<BiedStore>>,
+) {
+    let mut interval = tokio::time::interval(SYNC_INTERVAL);
+    loop {
+        interval.tick().await;
+        sync_offers(&cache, api.as_ref(), &store, SyncRequest::default()).await;
+    }
+}
+
+/// Sends the notifications that are due, all of a user's in as few messages as fit.
+pub async fn deliver_due(bot: &Bot, store: &Mutex<BiedStore>, now: DateTime<Utc>) {
+    let due = {
+        let store = store.lock().await;
+        let outboxes = match store.fetch_outboxes() {
+            Ok(outboxes) => outboxes,
+            Err(e) => {
+                log::warn!("couldn't load the notifications: {e}");
+                return;
+            }
+        };
+        outboxes
+            .into_iter()
+            .filter_map(|(user, outbox)| {
+                let prefs = store.fetch_preferences(user).unwrap_or_default();
+                outbox
+                    .is_due(&prefs, now)
+                    .then(|| (user, prefs.notification_lang(), outbox.pending))
+            })
+            .collect::<Vec<_>>()
+    };
+
+    for (user, lang, pending) in due {
+        let mut ids = Vec::new();
+        for (text, delivers) in render(lang, &pending) {
+            match bot.send_message(UserId(user), text).await {
+                Ok(_) => ids.extend(delivers),
+                Err(e) if is_permanent(&e) => {
+                    // retrying would only grow the queue, the user can't be reached
+                    log::info!("dropping the notifications of {user}: {e}");
+                    ids = pending.iter().map(|n| n.id.clone()).collect();
+                    break;
+                }
+                Err(e) => {
+                    // the rest stays queued for the next round
+                    log::warn!("couldn't notify {user}: {e}");
+                    break;
+                }
+            }
+        }
+        if ids.is_empty() {
+            continue;
+        }
+        let mut store = store.lock().await;
+        let result = store.fetch_outbox(user).and_then(|mut outbox| {
+            outbox.mark_delivered(&ids, now);
+            store.save_outbox(user, &outbox)
+        });
+        if let Err(e) = result {
+            log::warn!("couldn't mark notifications of {user} as delivered: {e}");
+        }
+    }
+}
+
+/// Errors that sending again won't fix, like a blocked bot or a user
+/// who never started a private chat with it.
+fn is_permanent(e: &RequestError) -> bool {
+    matches!(
+        e,
+        RequestError::Api(
+            ApiError::BotBlocked
+                | ApiError::BotKicked
+                | ApiError::CantInitiateConversation
+                | ApiError::ChatNotFound
+                | ApiError::UserNotFound
+                | ApiError::UserDeactivated
+                | ApiError::MessageIsTooLong
+        )
+    )
+}
+
+/// Splits the notifications into messages telegram accepts, each with the ids
+/// it delivers. A notification too long for one message is split as well and
+/// counts as delivered with its last part.
+fn render(lang: Lang, notifications: &[Notification]) -> Vec<(String, Vec<String>)> {
+    let mut messages = Vec::new();
+    let mut text = lang.texts().notifications.to_string();
+    let mut len = text.chars().count();
+    let mut ids = Vec::new();
+    for notification in notifications {
+        let chars = notification.text.chars().collect::<Vec<_>>();
+        for part in chars.chunks(MAX_MESSAGE_LEN) {
+            if len > 0 && len + 1 + part.len() > MAX_MESSAGE_LEN {
+                messages.push((std::mem::take(&mut text), std::mem::take(&mut ids)));
+                len = 0;
+            }
+            if len > 0 {
+                text.push('\n');
+                len += 1;
+            }
+            text.extend(part);
+            len += part.len();
+        }
+        ids.push(notification.id.clone());
+    }
+    messages.push((text, ids));
+    messages
+}
+
+/// Delivers the queued notifications until the bot stops.
+pub async fn run(bot: Bot, store: Arc<Mutex<BiedStore>>) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        deliver_due(&bot, &store, Utc::now()).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fake::synced_cache,
+        preferences::{Digest, QuietHours},
+    };
+
+    fn at(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(time)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn notification(id: &str, queued_at: DateTime<Utc>) -> Notification {
+        Notification {
+            id: id.to_string(),
+            text: id.to_string(),
+            queued_at: queued_at.timestamp(),
+        }
+    }
+
+    #[test]
+    fn holds_notifications_for_quiet_hours_and_digests() {
+        let queued = at("2022-10-19T01:00:00Z");
+        let mut outbox = Outbox::default();
+        outbox.push(notification("1001", queued));
+        outbox.push(notification("1001", queued));
+        assert_eq!(outbox.pending.len(), 1);
+
+        let mut prefs = Preferences {
+            quiet_hours: Some(QuietHours { from: 22, to: 7 }),
+            ..Default::default()
+        };
+        // 03:00 and 08:00 in Warsaw
+        assert!(!outbox.is_due(&prefs, at("2022-10-19T01:00:00Z")));
+        assert!(outbox.is_due(&prefs, at("2022-10-19T06:00:00Z")));
+
+        prefs.digest = Some(Digest {
+            hour: 18,
+            weekday: None,
+        });
+        assert!(!outbox.is_due(&prefs, at("2022-10-19T06:00:00Z")));
+        assert!(outbox.is_due(&prefs, at("2022-10-19T16:00:00Z")));
+
+        outbox.mark_delivered(&["1001".to_string()], at("2022-10-19T16:00:00Z"));
+        outbox.push(notification("1001", queued));
+        assert!(outbox.pending.is_empty());
+    }
+
+    #[test]
+    fn splits_long_notifications_into_messages() {
+        let queued = at("2022-10-19T01:00:00Z");
+        let mut long = notification("1002", queued);
+        long.text = "a".repeat(MAX_MESSAGE_LEN + 10);
+        let pending = [
+            notification("1001", queued),
+            long,
+            notification("1003", queued),
+        ];
+
+        let messages = render(Lang::En, &pending);
+        assert_eq!(messages.len(), 3);
+        assert!(messages
+            .iter()
+            .all(|(text, _)| text.chars().count() <= MAX_MESSAGE_LEN));
+        assert!(messages[0].0.ends_with("\n1001"));
+        assert_eq!(messages[0].1, ["1001"]);
+        assert!(messages[1].1.is_empty());
+        assert_eq!(messages[2].0, format!("{}\n1003", "a".repeat(10)));
+        assert_eq!(messages[2].1, ["1002", "1003"]);
+    }
+
+    #[tokio::test]
+    async fn queues_alerts_for_shopping_lists() {
+        let cache = synced_cache(&["jan", "anna"]).await;
+        let mut store = BiedStore::temporary();
+        store
+            .save_shopping_list(1, &["mleko".to_string(), "chleb".to_string()])
+            .unwrap();
+        store.save_shopping_list(2, &["kawa".to_string()]).unwrap();
+        let picky = Preferences {
+            min_discount: 40,
+            ..Default::default()
+        };
+        store.save_preferences(2, &picky).unwrap();
+
+        let now = at("2022-10-19T06:00:00Z");
+        queue_offer_alerts(&mut store, &cache.snapshot(), now).unwrap();
+        queue_offer_alerts(&mut store, &cache.snapshot(), now).unwrap();
+
+        let pending = store.fetch_outbox(1).unwrap().pending;
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].id, "1001");
+        assert!(pending[0].text.starts_with("mleko: Mleko UHT 3,2%"));
+        assert!(pending[0].text.ends_with("(-30%, anna, jan)"));
+        // kawa is only 33% off
+        assert!(store.fetch_outbox(2).unwrap().pending.is_empty());
+    }
+}
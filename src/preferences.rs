@@ -0,0 +1,254 @@
+use std::{fmt::Display, str::FromStr};
+
+use chrono::{DateTime, Datelike, TimeZone, Utc, Weekday};
+use chrono_tz::Europe::Warsaw;
+use serde::{de::Error, Deserialize, Deserializer, Serialize};
+
+use crate::i18n::Lang;
+
+/// Settings of a telegram user, edited with `/settings`.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Preferences {
+    /// Follows the telegram app when not set.
+    pub language: Option<Lang>,
+    /// Account used by `/balance` and `/spending` when no title is given.
+    pub default_account: Option<String>,
+    /// Notifications are batched into a digest instead of sent right away.
+    pub digest: Option<Digest>,
+    pub quiet_hours: Option<QuietHours>,
+    /// Only offers with at least this discount are notified about.
+    pub min_discount: i32,
+    /// Send offers as text instead of photos.
+    pub compact: bool,
+    /// Language of the telegram app, noted for the notifications which
+    /// aren't replies to a message that would carry it.
+    pub app_language: Option<Lang>,
+}
+
+/// Hours without notifications, may span midnight.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QuietHours {
+    pub from: u32,
+    pub to: u32,
+}
+
+impl QuietHours {
+    pub fn contains(&self, hour: u32) -> bool {
+        if self.from <= self.to {
+            (self.from..self.to).contains(&hour)
+        } else {
+            hour >= self.from || hour < self.to
+        }
+    }
+}
+
+impl Display for QuietHours {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:02}:00-{:02}:00", self.from, self.to)
+    }
+}
+
+/// When digests are sent, in Polish time.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Digest {
+    #[serde(deserialize_with = "deserialize_hour")]
+    pub hour: u32,
+    /// Weekly on this day, daily if not set.
+    pub weekday: Option<Weekday>,
+}
+
+fn deserialize_hour<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    let hour = u32::deserialize(deserializer)?;
+    if hour >= 24 {
+        return Err(D::Error::custom(format!("invalid hour {hour}")));
+    }
+    Ok(hour)
+}
+
+impl Digest {
+    /// The latest time the digest was due at before `now`, `None` for an invalid hour.
+    pub fn last_slot(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let now = now.with_timezone(&Warsaw);
+        // eight days back reach a weekly slot even when today's hasn't come yet
+        std::iter::successors(Some(now.date_naive()), |day| day.pred_opt())
+            .take(8)
+            .filter(|day| self.weekday.is_none_or(|w| day.weekday() == w))
+            .find_map(|day| {
+                let slot = Warsaw
+                    .from_local_datetime(&day.and_hms_opt(self.hour, 0, 0)?)
+                    .earliest()?;
+                (slot <= now).then(|| slot.with_timezone(&Utc))
+            })
+    }
+}
+
+const fn daily(hour: u32) -> Option<Digest> {
+    Some(Digest {
+        hour,
+        weekday: None,
+    })
+}
+
+const fn weekly(weekday: Weekday, hour: u32) -> Option<Digest> {
+    Some(Digest {
+        hour,
+        weekday: Some(weekday),
+    })
+}
+
+const DIGESTS: [Option<Digest>; 7] = [
+    None,
+    daily(7),
+    daily(8),
+    daily(18),
+    daily(20),
+    weekly(Weekday::Mon, 8),
+    weekly(Weekday::Sat, 9),
+];
+const QUIET_HOURS: [Option<QuietHours>; 4] = [
+    None,
+    Some(QuietHours { from: 22, to: 7 }),
+    Some(QuietHours { from: 23, to: 8 }),
+    Some(QuietHours { from: 21, to: 9 }),
+];
+const MIN_DISCOUNTS: [i32; 5] = [0, 20, 30, 40, 50];
+
+/// One row of the `/settings` menu.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Setting {
+    Language,
+    Display,
+    MinDiscount,
+    Digest,
+    QuietHours,
+    DefaultAccount,
+}
+
+impl Setting {
+    pub const ALL: [Setting; 6] = [
+        Setting::Language,
+        Setting::Display,
+        Setting::MinDiscount,
+        Setting::Digest,
+        Setting::QuietHours,
+        Setting::DefaultAccount,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Setting::Language => "language",
+            Setting::Display => "display",
+            Setting::MinDiscount => "discount",
+            Setting::Digest => "digest",
+            Setting::QuietHours => "quiet",
+            Setting::DefaultAccount => "account",
+        }
+    }
+}
+
+impl FromStr for Setting {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Setting::ALL
+            .into_iter()
+            .find(|e| e.name() == s)
+            .ok_or_else(|| format!("unknown setting `{s}`"))
+    }
+}
+
+impl Display for Setting {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// The option after `current`, or the first one.
+fn next<T: PartialEq + Clone>(options: &[T], current: &T) -> T {
+    let i = options
+        .iter()
+        .position(|e| e == current)
+        .map_or(0, |i| i + 1);
+    options[i % options.len()].clone()
+}
+
+impl Preferences {
+    /// Moves the setting to its next value, `accounts` are the titles to pick the default from.
+    pub fn cycle(&mut self, setting: Setting, accounts: &[String]) {
+        match setting {
+            Setting::Language => {
+                let options = [None, Some(Lang::En), Some(Lang::Pl)];
+                self.language = next(&options, &self.language);
+            }
+            Setting::Display => self.compact = !self.compact,
+            Setting::MinDiscount => self.min_discount = next(&MIN_DISCOUNTS, &self.min_discount),
+            Setting::Digest => self.digest = next(&DIGESTS, &self.digest),
+            Setting::QuietHours => self.quiet_hours = next(&QUIET_HOURS, &self.quiet_hours),
+            Setting::DefaultAccount => {
+                let options = [None]
+                    .into_iter()
+                    .chain(accounts.iter().cloned().map(Some))
+                    .collect::<Vec<_>>();
+                self.default_account = next(&options, &self.default_account);
+            }
+        }
+    }
+
+    /// The default account, unless it has been removed since.
+    pub fn default_account<'a>(&'a self, accounts: &[String]) -> Option<&'a str> {
+        self.default_account
+            .as_deref()
+            .filter(|title| accounts.iter().any(|a| a == title))
+    }
+
+    /// Language of the notifications, with the same fallback as the replies.
+    pub fn notification_lang(&self) -> Lang {
+        self.language.or(self.app_language).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cycles_through_the_options() {
+        let accounts = ["anna".to_string(), "jan".to_string()];
+        let mut prefs = Preferences::default();
+        prefs.cycle(Setting::DefaultAccount, &accounts);
+        prefs.cycle(Setting::DefaultAccount, &accounts);
+        assert_eq!(prefs.default_account(&accounts), Some("jan"));
+        prefs.cycle(Setting::DefaultAccount, &accounts);
+        assert_eq!(prefs.default_account, None);
+
+        prefs.default_account = Some("removed".to_string());
+        assert_eq!(prefs.default_account(&accounts), None);
+
+        prefs.quiet_hours = QUIET_HOURS[3];
+        prefs.cycle(Setting::QuietHours, &accounts);
+        assert_eq!(prefs.quiet_hours, None);
+        prefs.app_language = Some(Lang::Pl);
+        assert_eq!(prefs.notification_lang(), Lang::Pl);
+        prefs.cycle(Setting::Language, &accounts);
+        assert_eq!(prefs.language, Some(Lang::En));
+        assert_eq!(prefs.notification_lang(), Lang::En);
+        assert_eq!("quiet".parse(), Ok(Setting::QuietHours));
+    }
+
+    #[test]
+    fn finds_the_last_digest_slot() {
+        // a Wednesday, 10:30 in Warsaw
+        let now = DateTime::parse_from_rfc3339("2022-10-19T08:30:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let slot = |digest: Option<Digest>| digest.unwrap().last_slot(now).unwrap().to_rfc3339();
+        assert_eq!(slot(daily(8)), "2022-10-19T06:00:00+00:00");
+        assert_eq!(slot(daily(18)), "2022-10-18T16:00:00+00:00");
+        assert_eq!(slot(weekly(Weekday::Mon, 8)), "2022-10-17T06:00:00+00:00");
+        assert_eq!(daily(24).unwrap().last_slot(now), None);
+        let invalid = bincode::serialize(&(24u32, None::<Weekday>)).unwrap();
+        assert!(bincode::deserialize::<Digest>(&invalid).is_err());
+        assert!(QuietHours { from: 22, to: 7 }.contains(3));
+        assert!(!QuietHours { from: 22, to: 7 }.contains(7));
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::api::Offer;
+
+const NAME_WEIGHT: u32 = 3;
+const DETAILS_WEIGHT: u32 = 1;
+
+#[derive(Default)]
+pub struct OfferIndex {
+    /// Account and offer id of every indexed offer.
+    entries: Vec<(String, String)>,
+    tokens: BTreeMap<String, Vec<(usize, u32)>>,
+}
+
+pub struct OfferHit<'a> {
+    pub account: &'a str,
+    pub id: &'a str,
+    pub score: u32,
+}
+
+impl OfferIndex {
+    pub fn build(offers: &HashMap<String, Vec<Offer>>) -> Self {
+        let mut index = Self::default();
+        for (account, offers) in offers {
+            for offer in offers {
+                let entry = index.entries.len();
+                index
+                    .entries
+                    .push((account.clone(), offer.id().to_string()));
+                index.add(entry, &offer.name, NAME_WEIGHT);
+                index.add(entry, &offer.details, DETAILS_WEIGHT);
+                index.add(entry, &offer.tags.join(" "), DETAILS_WEIGHT);
+            }
+        }
+        index
+    }
+
+    fn add(&mut self, entry: usize, text: &str, weight: u32) {
+        for token in tokenize(text) {
+            let postings = self.tokens.entry(token).or_default();
+            match postings.last_mut() {
+                Some((e, w)) if *e == entry => *w = (*w).max(weight),
+                _ => postings.push((entry, weight)),
+            }
+        }
+    }
+
+    /// Finds offers matching every term of the query by prefix, best matches first.
+    /// An empty query matches everything.
+    pub fn search(&self, query: &str) -> Vec<OfferHit<'_>> {
+        let terms = tokenize(query);
+        let mut scores = vec![(0u32, 0usize); self.entries.len()];
+        for term in &terms {
+            let mut term_scores = HashMap::<usize, u32>::new();
+            for (_, postings) in self
+                .tokens
+                .range(term.clone()..)
+                .take_while(|(token, _)| token.starts_with(term.as_str()))
+            {
+                for (entry, weight) in postings {
+                    let score = term_scores.entry(*entry).or_default();
+                    *score = (*score).max(*weight);
+                }
+            }
+            for (entry, score) in term_scores {
+                scores[entry].0 += score;
+                scores[entry].1 += 1;
+            }
+        }
+
+        let mut hits = scores
+            .into_iter()
+            .enumerate()
+            .filter(|(_, (_, matched))| *matched == terms.len())
+            .map(|(entry, (score, _))| OfferHit {
+                account: &self.entries[entry].0,
+                id: &self.entries[entry].1,
+                score,
+            })
+            .collect::<Vec<_>>();
+        hits.sort_by_key(|e| std::cmp::Reverse(e.score));
+        hits
+    }
+}
+
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .chars()
+        .map(fold_diacritic)
+        .collect::<String>()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|e| !e.is_empty())
+        .map(|e| e.to_string())
+        .collect()
+}
+
+fn fold_diacritic(c: char) -> char {
+    match c {
+        'ą' => 'a',
+        'ć' => 'c',
+        'ę' => 'e',
+        'ł' => 'l',
+        'ń' => 'n',
+        'ó' => 'o',
+        'ś' => 's',
+        'ź' | 'ż' => 'z',
+        c => c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{api::parse_offers, fake::OFFERS_FIXTURE};
+
+    #[test]
+    fn folds_polish_diacritics() {
+        assert_eq!(
+            tokenize("Żółta KAWA, 3,2% łączna"),
+            ["zolta", "kawa", "3", "2", "laczna"]
+        );
+    }
+
+    #[test]
+    fn matches_every_term_by_prefix_names_first() {
+        let offers = parse_offers(OFFERS_FIXTURE).unwrap();
+        let mut cream = offers[0].clone();
+        cream.name = "Śmietanka".to_string();
+        cream.details = "do kawy".to_string();
+        cream.tags.clear();
+        let index = OfferIndex::build(&HashMap::from([
+            ("anna".to_string(), offers),
+            ("jan".to_string(), vec![cream]),
+        ]));
+        let found = |query: &str| {
+            index
+                .search(query)
+                .into_iter()
+                .map(|hit| (hit.account.to_string(), hit.id.to_string()))
+                .collect::<Vec<_>>()
+        };
+
+        let hit = |account: &str, id: &str| (account.to_string(), id.to_string());
+
+        assert_eq!(found("kaw"), [hit("anna", "1002"), hit("jan", "1001")]);
+        assert_eq!(found("smietan"), [hit("jan", "1001")]);
+        assert_eq!(found("mleko karta"), [hit("anna", "1001")]);
+        assert!(found("mleko kawa").is_empty());
+        assert_eq!(found("").len(), 3);
+    }
+}
@@ -1,22 +1,332 @@
 use serde::{Deserialize, Serialize};
-use std::fs;
+use std::{collections::BTreeMap, fmt::Display, fs, io, net::SocketAddr, path::Path};
+use toml::Value;
 
-pub fn get_secrets() -> Secrets {
-    toml::from_str(&fs::read_to_string("secrets.toml").unwrap()).unwrap()
+const ENV_PREFIX: &str = "BIEDBOT";
+
+/// Profile of `api_config`, used by accounts added without one.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Offers expire when the day changes in Poland anyway.
+const MAX_TTL_MINUTES: i64 = 24 * 60;
+
+/// More retries would only keep a request hanging, as the backoff is capped at a minute.
+const MAX_RETRIES: u32 = 10;
+
+enum FieldKind {
+    String,
+    Bool,
+    Integer,
+    IntegerList,
+}
+
+// every config field, so each one can be overridden from the environment,
+// except `api_config.http.headers` and `api_profiles`, whose keys are user defined
+const FIELDS: &[(&str, FieldKind, bool)] = &[
+    ("ean_frontend", FieldKind::String, true),
+    ("cdn_root", FieldKind::String, true),
+    ("card_qr", FieldKind::Bool, false),
+    ("telegram_config.bot_token", FieldKind::String, true),
+    (
+        "telegram_config.maintainer_ids",
+        FieldKind::IntegerList,
+        true,
+    ),
+    ("telegram_config.webhook.address", FieldKind::String, false),
+    ("telegram_config.webhook.url", FieldKind::String, false),
+    (
+        "telegram_config.webhook.secret_token",
+        FieldKind::String,
+        false,
+    ),
+    ("telegram_config.webhook.register", FieldKind::Bool, false),
+    ("api_config.api_root", FieldKind::String, true),
+    ("api_config.brand_name", FieldKind::String, true),
+    ("api_config.module_version", FieldKind::String, true),
+    ("api_config.promo_sync_api_version", FieldKind::String, true),
+    (
+        "api_config.activate_offer_api_version",
+        FieldKind::String,
+        false,
+    ),
+    ("api_config.account_api_version", FieldKind::String, false),
+    ("api_config.history_api_version", FieldKind::String, false),
+    (
+        "api_config.http.connect_timeout_secs",
+        FieldKind::Integer,
+        false,
+    ),
+    (
+        "api_config.http.request_timeout_secs",
+        FieldKind::Integer,
+        false,
+    ),
+    ("api_config.http.max_retries", FieldKind::Integer, false),
+    (
+        "api_config.http.retry_backoff_ms",
+        FieldKind::Integer,
+        false,
+    ),
+    ("api_config.http.proxy", FieldKind::String, false),
+    ("cache_config.ttl_minutes", FieldKind::Integer, false),
+    ("cache_config.sync_parallelism", FieldKind::Integer, false),
+];
+
+/// Environment variable overriding the field, e.g. `BIEDBOT_TELEGRAM_CONFIG_BOT_TOKEN`.
+fn env_var(path: &str) -> String {
+    format!("{ENV_PREFIX}_{}", path.replace('.', "_").to_uppercase())
+}
+
+fn parse_env(kind: &FieldKind, value: &str) -> Result<Value, String> {
+    match kind {
+        FieldKind::String => Ok(Value::String(value.to_string())),
+        FieldKind::Bool => value
+            .parse()
+            .map(Value::Boolean)
+            .map_err(|_| format!("`{value}` is not a boolean")),
+        FieldKind::Integer => value
+            .parse()
+            .map(Value::Integer)
+            .map_err(|_| format!("`{value}` is not an integer")),
+        FieldKind::IntegerList => value
+            .split(',')
+            .map(|e| e.trim().parse().map(Value::Integer))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Value::Array)
+            .map_err(|_| format!("`{value}` is not a comma separated list of integers")),
+    }
+}
+
+fn matches_kind(kind: &FieldKind, value: &Value) -> bool {
+    match (kind, value) {
+        (FieldKind::String, Value::String(_))
+        | (FieldKind::Bool, Value::Boolean(_))
+        | (FieldKind::Integer, Value::Integer(_)) => true,
+        (FieldKind::IntegerList, Value::Array(a)) => a.iter().all(|e| e.is_integer()),
+        _ => false,
+    }
+}
+
+fn get<'a>(config: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(config, |v, key| v.get(key))
+}
+
+fn set(config: &mut Value, path: &str, value: Value) {
+    let mut keys = path.split('.').peekable();
+    let mut table = config;
+    while let Some(key) = keys.next() {
+        let Value::Table(t) = table else {
+            return;
+        };
+        if keys.peek().is_none() {
+            t.insert(key.to_string(), value);
+            return;
+        }
+        table = t
+            .entry(key.to_string())
+            .or_insert_with(|| Value::Table(Default::default()));
+    }
+}
+
+fn read_config(path: &Path) -> Result<(String, Value), ConfigError> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        // everything can come from the environment instead
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(ConfigError(format!("{}: {e}", path.display()))),
+    };
+    let config = text.parse::<Value>().map_err(|e| {
+        ConfigError(match e.line_col() {
+            Some((line, _)) => format!("{}:{}: {e}", path.display(), line + 1),
+            None => format!("{}: {e}", path.display()),
+        })
+    })?;
+    Ok((text, config))
+}
+
+/// Reads a variable of the process environment.
+fn process_env(var: &str) -> Option<String> {
+    std::env::var(var).ok()
+}
+
+/// `env` looks the variables up, the process environment outside of tests.
+fn apply_env(config: &mut Value, env: impl Fn(&str) -> Option<String>) -> Vec<String> {
+    let mut problems = vec![];
+    for (path, kind, _) in FIELDS {
+        let var = env_var(path);
+        if let Some(value) = env(&var) {
+            match parse_env(kind, &value) {
+                Ok(value) => set(config, path, value),
+                Err(e) => problems.push(format!("{var}: {e}")),
+            }
+        }
+    }
+    problems
+}
+
+pub fn load_secrets(path: &Path) -> Result<Secrets, ConfigError> {
+    load_secrets_with(path, process_env)
+}
+
+fn load_secrets_with(
+    path: &Path,
+    env: impl Fn(&str) -> Option<String>,
+) -> Result<Secrets, ConfigError> {
+    let (_, mut config) = read_config(path)?;
+    if let Some(problem) = apply_env(&mut config, env).into_iter().next() {
+        return Err(ConfigError(problem));
+    }
+    let secrets: Secrets = config
+        .try_into()
+        .map_err(|e| ConfigError(format!("{}: {e}", path.display())))?;
+    if let Some(problem) = value_problems(path, &secrets).into_iter().next() {
+        return Err(ConfigError(problem));
+    }
+    Ok(secrets)
+}
+
+/// Problems with values that parse fine but can't be used.
+fn value_problems(path: &Path, secrets: &Secrets) -> Vec<String> {
+    let mut problems = vec![];
+    if secrets.api_profiles.contains_key(DEFAULT_PROFILE) {
+        problems.push(format!(
+            "{}: `api_profiles.{DEFAULT_PROFILE}` is reserved for `api_config`",
+            path.display()
+        ));
+    }
+    let apis = [("api_config".to_string(), &secrets.api_config)]
+        .into_iter()
+        .chain(
+            secrets
+                .api_profiles
+                .iter()
+                .map(|(name, api)| (format!("api_profiles.{name}"), api)),
+        );
+    for (name, api) in apis {
+        if api.http.max_retries > MAX_RETRIES {
+            problems.push(format!(
+                "{}: `{name}.http.max_retries` should be at most {MAX_RETRIES}",
+                path.display()
+            ));
+        }
+    }
+    if !(0..=MAX_TTL_MINUTES).contains(&secrets.cache_config.ttl_minutes) {
+        problems.push(format!(
+            "{}: `cache_config.ttl_minutes` should be between 0 and {MAX_TTL_MINUTES}",
+            path.display()
+        ));
+    }
+    problems
+}
+
+/// Lists every problem with the config, pointing at the line of the file where possible.
+pub fn check_secrets(path: &Path) -> Result<Vec<String>, ConfigError> {
+    check_secrets_with(path, process_env)
+}
+
+fn check_secrets_with(
+    path: &Path,
+    env: impl Fn(&str) -> Option<String>,
+) -> Result<Vec<String>, ConfigError> {
+    let (text, mut config) = read_config(path)?;
+    let mut problems = apply_env(&mut config, env);
+    for (field, kind, required) in FIELDS {
+        match get(&config, field) {
+            None if *required => problems.push(format!(
+                "{}: missing field `{field}` (or {})",
+                path.display(),
+                env_var(field)
+            )),
+            Some(value) if !matches_kind(kind, value) => problems.push(format!(
+                "{}:{}: `{field}` should be {}, found {}",
+                path.display(),
+                find_line(&text, field).map_or("?".to_string(), |l| l.to_string()),
+                match kind {
+                    FieldKind::String => "a string",
+                    FieldKind::Bool => "a boolean",
+                    FieldKind::Integer => "an integer",
+                    FieldKind::IntegerList => "a list of integers",
+                },
+                value.type_str()
+            )),
+            _ => {}
+        }
+    }
+    if problems.is_empty() {
+        match config.try_into::<Secrets>() {
+            Ok(secrets) => problems.extend(value_problems(path, &secrets)),
+            Err(e) => problems.push(format!("{}: {e}", path.display())),
+        }
+    }
+    Ok(problems)
+}
+
+/// Line number of a `table.key = ...` assignment in the config file.
+fn find_line(text: &str, path: &str) -> Option<usize> {
+    let (table, key) = path.rsplit_once('.').unwrap_or(("", path));
+    let mut current = "";
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            current = header.trim();
+        } else if current == table
+            && line
+                .split_once('=')
+                .is_some_and(|(k, _)| k.trim().trim_matches('"') == key)
+        {
+            return Some(i + 1);
+        }
+    }
+    None
+}
+
+#[derive(Debug)]
+pub struct ConfigError(String);
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
 }
 
 #[derive(Deserialize, Serialize)]
 pub struct Secrets {
     pub telegram_config: TelegramConfig,
     pub api_config: ApiConfig,
+    /// Apis of other brands running on the same platform, keyed by profile name.
+    #[serde(default)]
+    pub api_profiles: BTreeMap<String, ApiConfig>,
     pub ean_frontend: String,
     pub cdn_root: String,
+    #[serde(default)]
+    pub card_qr: bool,
+    #[serde(default)]
+    pub cache_config: CacheConfig,
 }
 
 #[derive(Deserialize, Serialize)]
 pub struct TelegramConfig {
     pub bot_token: String,
     pub maintainer_ids: Vec<u64>,
+    /// Long polling is used when unset.
+    pub webhook: Option<WebhookConfig>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct WebhookConfig {
+    /// Local address the listener binds to, e.g. `127.0.0.1:8443`.
+    pub address: SocketAddr,
+    /// Public url telegram posts to, its path is the one served locally.
+    pub url: String,
+    /// Checked against the `X-Telegram-Bot-Api-Secret-Token` header of every update.
+    pub secret_token: Option<String>,
+    /// Whether to call `setWebhook` on startup and remove the webhook on shutdown.
+    #[serde(default = "register_default")]
+    pub register: bool,
+}
+
+fn register_default() -> bool {
+    true
 }
 
 #[derive(Deserialize, Serialize)]
@@ -25,4 +335,147 @@ pub struct ApiConfig {
     pub brand_name: String,
     pub module_version: String,
     pub promo_sync_api_version: String,
+    /// Defaults to `promo_sync_api_version`.
+    #[serde(default)]
+    pub activate_offer_api_version: Option<String>,
+    /// Defaults to `promo_sync_api_version`.
+    #[serde(default)]
+    pub account_api_version: Option<String>,
+    /// Defaults to `promo_sync_api_version`.
+    #[serde(default)]
+    pub history_api_version: Option<String>,
+    #[serde(default)]
+    pub http: HttpConfig,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(default)]
+pub struct HttpConfig {
+    pub connect_timeout_secs: u64,
+    /// Timeout of a whole request, including reading the response.
+    pub request_timeout_secs: u64,
+    /// How many times a timed out or 5xx request is retried.
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled after every attempt.
+    pub retry_backoff_ms: u64,
+    /// e.g. `http://proxy:3128` or `socks5://127.0.0.1:9050`.
+    pub proxy: Option<String>,
+    /// Sent with every api request, e.g. `User-Agent`.
+    pub headers: BTreeMap<String, String>,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout_secs: 10,
+            request_timeout_secs: 30,
+            max_retries: 3,
+            retry_backoff_ms: 500,
+            proxy: None,
+            headers: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    /// How long synced offers are reused before `/sync` fetches them again.
+    pub ttl_minutes: i64,
+    /// How many accounts are synced at the same time.
+    pub sync_parallelism: usize,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            ttl_minutes: 360,
+            sync_parallelism: 4,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    const CONFIG: &str = r#"ean_frontend = "https://example.com/ean/"
+cdn_root = "file"
+card_qr = false
+
+[telegram_config]
+bot_token = "token"
+maintainer_ids = [1]
+
+[api_config]
+api_root = "https://example.com/api/"
+brand_name = "Test"
+module_version = "1"
+promo_sync_api_version = "1"
+
+[api_config.http]
+proxy = "socks5://127.0.0.1:9050"
+"#;
+
+    #[test]
+    fn finds_lines_of_nested_keys() {
+        assert_eq!(find_line(CONFIG, "cdn_root"), Some(2));
+        assert_eq!(find_line(CONFIG, "api_config.http.proxy"), Some(16));
+        assert_eq!(find_line(CONFIG, "api_config.proxy"), None);
+        assert_eq!(find_line(CONFIG, "telegram_config.bot_token"), Some(6));
+    }
+
+    #[test]
+    fn bounds_retries() {
+        let path = Path::new("secrets.toml");
+        let secrets: Secrets = toml::from_str(
+            &CONFIG.replace("[api_config.http]", "[api_config.http]\nmax_retries = 65"),
+        )
+        .unwrap();
+        assert_eq!(
+            value_problems(path, &secrets),
+            ["secrets.toml: `api_config.http.max_retries` should be at most 10"]
+        );
+        let secrets: Secrets = toml::from_str(CONFIG).unwrap();
+        assert!(value_problems(path, &secrets).is_empty());
+    }
+
+    fn lookup<'a>(env: &'a HashMap<&str, &str>) -> impl Fn(&str) -> Option<String> + 'a {
+        move |var| env.get(var).map(|v| v.to_string())
+    }
+
+    #[test]
+    fn environment_overrides_the_file() {
+        let path = std::env::temp_dir().join(format!("biedbot-{}.toml", std::process::id()));
+        fs::write(&path, CONFIG).unwrap();
+        // a map instead of the process environment, which other tests read concurrently
+        let mut env = HashMap::from([
+            ("BIEDBOT_CDN_ROOT", "env"),
+            ("BIEDBOT_TELEGRAM_CONFIG_MAINTAINER_IDS", "2, 3"),
+        ]);
+        let secrets = load_secrets_with(&path, lookup(&env)).unwrap();
+        assert_eq!(secrets.cdn_root, "env");
+        assert_eq!(secrets.telegram_config.maintainer_ids, [2, 3]);
+        assert_eq!(secrets.ean_frontend, "https://example.com/ean/");
+        assert!(check_secrets_with(&path, lookup(&env)).unwrap().is_empty());
+
+        env.insert("BIEDBOT_CARD_QR", "maybe");
+        fs::write(&path, CONFIG.replace("[1]", "\"1\"")).unwrap();
+        // the valid override hides the malformed file value
+        assert_eq!(
+            check_secrets_with(&path, lookup(&env)).unwrap(),
+            ["BIEDBOT_CARD_QR: `maybe` is not a boolean"]
+        );
+        env.remove("BIEDBOT_TELEGRAM_CONFIG_MAINTAINER_IDS");
+        assert_eq!(
+            check_secrets_with(&path, lookup(&env)).unwrap()[1],
+            format!(
+                "{}:7: `telegram_config.maintainer_ids` should be a list of integers, found string",
+                path.display()
+            )
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
 }
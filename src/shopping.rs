@@ -0,0 +1,66 @@
+use crate::{api::Offer, cache::OfferSnapshot, i18n::Lang};
+
+const OFFERS_PER_ITEM: usize = 2;
+
+/// Best offers matching the item and the accounts (cards) they're available on.
+pub fn matching_offers<'a>(
+    item: &str,
+    snapshot: &'a OfferSnapshot,
+) -> Vec<(&'a Offer, Vec<&'a str>)> {
+    let mut matches: Vec<(&Offer, Vec<&str>)> = Vec::new();
+    for (account, offer) in snapshot.search(item) {
+        let same = matches
+            .iter()
+            .position(|(o, _)| o.name == offer.name && o.offer_price == offer.offer_price);
+        match same {
+            Some(i) => matches[i].1.push(account),
+            None if matches.len() < OFFERS_PER_ITEM => matches.push((offer, vec![account])),
+            None => {}
+        }
+    }
+    matches
+}
+
+/// The shopping list with the offers to use for every item.
+pub fn annotate(items: &[String], snapshot: &OfferSnapshot, lang: Lang) -> String {
+    let texts = lang.texts();
+    if items.is_empty() {
+        return texts.shopping_list_empty.to_string();
+    }
+    let mut lines = vec![texts.shopping_list.to_string()];
+    for (i, item) in items.iter().enumerate() {
+        lines.push(format!("{}. {item}", i + 1));
+        let matches = matching_offers(item, snapshot);
+        if matches.is_empty() {
+            lines.push(format!("    {}", texts.no_item_offers));
+        }
+        for (offer, mut accounts) in matches {
+            accounts.sort();
+            lines.push(format!(
+                "    {} (-{}%, {})",
+                offer.short_display(),
+                offer.discount_percent,
+                accounts.join(", ")
+            ));
+        }
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake::synced_cache;
+
+    #[tokio::test]
+    async fn annotates_items_with_offers_from_all_cards() {
+        let cache = synced_cache(&["jan", "anna"]).await;
+        let items = vec!["mleko".to_string(), "chleb".to_string()];
+        let text = annotate(&items, &cache.snapshot(), Lang::En);
+        let lines = text.lines().collect::<Vec<_>>();
+        assert_eq!(lines[1], "1. mleko");
+        assert!(lines[2].starts_with("    Mleko UHT 3,2%"));
+        assert!(lines[2].ends_with(", anna, jan)"));
+        assert_eq!(lines[3..], ["2. chleb", "    no offers"]);
+    }
+}
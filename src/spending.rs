@@ -0,0 +1,122 @@
+use std::{fmt::Display, str::FromStr};
+
+use chrono::{Datelike, NaiveDate};
+
+use crate::{
+    api::Transaction,
+    i18n::{fill, Lang, Localize},
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Month {
+    pub year: i32,
+    pub month: u32,
+}
+
+impl Month {
+    pub fn of(date: NaiveDate) -> Self {
+        Self {
+            year: date.year(),
+            month: date.month(),
+        }
+    }
+
+    /// Parses a command argument shaped like `YYYY-MM`, `None` for anything else.
+    pub fn parse_arg(arg: &str) -> Option<Result<Self, InvalidMonth>> {
+        let (year, month) = arg.split_once('-')?;
+        let digits = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+        (digits(year) && digits(month)).then(|| arg.parse())
+    }
+}
+
+/// `YYYY-MM`, e.g. `2022-10`.
+impl FromStr for Month {
+    type Err = InvalidMonth;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        NaiveDate::parse_from_str(&format!("{s}-01"), "%Y-%m-%d")
+            .map(Month::of)
+            .map_err(|_| InvalidMonth(s.to_string()))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidMonth(String);
+
+impl Localize for InvalidMonth {
+    fn localize(&self, lang: Lang) -> String {
+        fill(lang.texts().invalid_month, &[&self.0])
+    }
+}
+
+impl Display for InvalidMonth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.localize(Lang::En))
+    }
+}
+
+impl Display for Month {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{:02}", self.year, self.month)
+    }
+}
+
+/// Totals of the receipts of a month, amounts are in grosze.
+#[derive(Default, PartialEq, Eq, Debug)]
+pub struct Spending {
+    pub total: i64,
+    pub visits: usize,
+    pub savings: i64,
+}
+
+impl Spending {
+    pub fn of(transactions: &[Transaction], month: Month) -> Self {
+        transactions
+            .iter()
+            .filter(|t| Month::of(t.date) == month)
+            .fold(Spending::default(), |s, t| Spending {
+                total: s.total + i64::from(t.total),
+                visits: s.visits + 1,
+                savings: s.savings + i64::from(t.savings),
+            })
+    }
+
+    pub fn render(&self, lang: Lang) -> String {
+        fill(
+            lang.texts().spending,
+            &[
+                &lang.price(self.total),
+                &self.visits,
+                &lang.price(self.savings),
+            ],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{api::parse_transactions, fake::TRANSACTIONS_FIXTURE};
+
+    #[test]
+    fn sums_up_a_month() {
+        let transactions = parse_transactions(TRANSACTIONS_FIXTURE).unwrap();
+        let october = "2022-10".parse().unwrap();
+        let spending = Spending::of(&transactions, october);
+        assert_eq!(
+            spending,
+            Spending {
+                total: 19729,
+                visits: 2,
+                savings: 2105,
+            }
+        );
+        assert_eq!(
+            spending.render(Lang::En),
+            "PLN 197.29 in 2 visits, saved PLN 21.05 on promotions"
+        );
+        assert!(Month::parse_arg("2022-13").unwrap().is_err());
+        assert_eq!(Month::parse_arg("2022-10"), Some(Ok(october)));
+        assert_eq!(Month::parse_arg("anna"), None);
+    }
+}
@@ -0,0 +1,194 @@
+use crate::{
+    api::AuthenticatedUser,
+    barcode::{self, BarcodeError},
+    i18n::{fill, Lang, Localize},
+};
+
+const DEFAULT_COUNTRY_CODE: &str = "48";
+const NATIONAL_NUMBER_LEN: usize = 9;
+
+pub fn validate_card_number(card_number: &str) -> Result<String, ValidationError> {
+    barcode::parse_ean13(card_number)
+        .map(|_| card_number.trim().to_string())
+        .map_err(ValidationError::CardNumber)
+}
+
+/// Normalises a phone number to E.164. Numbers without a `+` or `00` prefix
+/// must be Polish, given with or without the country code.
+pub fn normalize_phone_number(phone_number: &str) -> Result<String, ValidationError> {
+    let compact = phone_number
+        .trim()
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '-' | '(' | ')' | '.'))
+        .collect::<String>();
+    let (digits, has_country_code) = if let Some(rest) = compact.strip_prefix('+') {
+        (rest, true)
+    } else if let Some(rest) = compact.strip_prefix("00") {
+        (rest, true)
+    } else {
+        (compact.as_str(), false)
+    };
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(ValidationError::PhoneCharacters(phone_number.to_string()));
+    }
+    // without a prefix only a Polish number is accepted, with or without its country code
+    let international = if has_country_code {
+        digits.to_string()
+    } else if digits.len() == NATIONAL_NUMBER_LEN {
+        format!("{DEFAULT_COUNTRY_CODE}{digits}")
+    } else if digits.starts_with(DEFAULT_COUNTRY_CODE) {
+        digits.to_string()
+    } else {
+        return Err(ValidationError::PhoneLength(
+            phone_number.to_string(),
+            digits.len(),
+        ));
+    };
+
+    if international.starts_with('0') || !(8..=15).contains(&international.len()) {
+        return Err(ValidationError::PhoneInternational(
+            phone_number.to_string(),
+        ));
+    }
+    if let Some(national) = international.strip_prefix(DEFAULT_COUNTRY_CODE) {
+        if national.len() != NATIONAL_NUMBER_LEN {
+            return Err(ValidationError::PhoneLength(
+                phone_number.to_string(),
+                national.len(),
+            ));
+        }
+    }
+    Ok(format!("+{international}"))
+}
+
+pub fn validate_title(title: &str) -> Result<(), ValidationError> {
+    if title.trim().is_empty() {
+        return Err(ValidationError::EmptyTitle);
+    }
+    Ok(())
+}
+
+/// Checks the profile is one of the configured `profiles`.
+pub fn validate_profile(profile: &str, profiles: &[String]) -> Result<(), ValidationError> {
+    if !profiles.iter().any(|p| p == profile) {
+        return Err(ValidationError::UnknownProfile(
+            profile.to_string(),
+            profiles.to_vec(),
+        ));
+    }
+    Ok(())
+}
+
+pub fn validate_account(user: AuthenticatedUser) -> Result<AuthenticatedUser, ValidationError> {
+    Ok(AuthenticatedUser {
+        card_number: validate_card_number(&user.card_number)?,
+        phone_number: normalize_phone_number(&user.phone_number)?,
+        ..user
+    })
+}
+
+/// The phone number variants hold the number as given.
+#[derive(Debug)]
+pub enum ValidationError {
+    CardNumber(BarcodeError),
+    PhoneCharacters(String),
+    PhoneInternational(String),
+    /// Number and how many national digits it has.
+    PhoneLength(String, usize),
+    EmptyTitle,
+    /// Profile and the configured ones.
+    UnknownProfile(String, Vec<String>),
+}
+
+impl Localize for ValidationError {
+    fn localize(&self, lang: Lang) -> String {
+        let texts = lang.texts();
+        match self {
+            ValidationError::CardNumber(e) => fill(texts.invalid_card_number, &[&e.localize(lang)]),
+            ValidationError::PhoneCharacters(n) => fill(texts.phone_characters, &[n]),
+            ValidationError::PhoneInternational(n) => fill(texts.phone_international, &[n]),
+            ValidationError::PhoneLength(n, len) => {
+                fill(texts.phone_length, &[&NATIONAL_NUMBER_LEN, n, len])
+            }
+            ValidationError::EmptyTitle => texts.empty_title.to_string(),
+            ValidationError::UnknownProfile(profile, profiles) => {
+                fill(texts.unknown_profile, &[profile, &profiles.join(", ")])
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.localize(Lang::En))
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_phone_numbers() {
+        let cases = [
+            ("500600700", Some("+48500600700")),
+            (" 500-600-700 ", Some("+48500600700")),
+            ("48500600700", Some("+48500600700")),
+            ("+48 500 600 700", Some("+48500600700")),
+            ("0048500600700", Some("+48500600700")),
+            ("+49 (30) 1234567", Some("+49301234567")),
+            ("5006007001", None),
+            ("12345678", None),
+            ("4850060070", None),
+            ("+4850060070", None),
+            ("+0123456789", None),
+            ("+1234", None),
+            ("500 600 7oo", None),
+            ("", None),
+        ];
+        for (number, expected) in cases {
+            assert_eq!(
+                normalize_phone_number(number).ok().as_deref(),
+                expected,
+                "{number}"
+            );
+        }
+        assert!(matches!(
+            normalize_phone_number("5006007001"),
+            Err(ValidationError::PhoneLength(_, 10))
+        ));
+        assert!(matches!(
+            normalize_phone_number("+"),
+            Err(ValidationError::PhoneCharacters(_))
+        ));
+    }
+
+    #[test]
+    fn validates_card_numbers_and_titles() {
+        let cards = [
+            (" 5901234123457 ", Some("5901234123457")),
+            ("5901234123458", None),
+            ("590123412345", None),
+            ("59012341234x7", None),
+        ];
+        for (card, expected) in cards {
+            assert_eq!(
+                validate_card_number(card).ok().as_deref(),
+                expected,
+                "{card}"
+            );
+        }
+
+        let titles = [
+            ("jan", true),
+            (" Jan Kowalski ", true),
+            ("", false),
+            ("  ", false),
+        ];
+        for (title, valid) in titles {
+            assert_eq!(validate_title(title).is_ok(), valid, "{title:?}");
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use std::{convert::Infallible, error::Error, future::Future, net::SocketAddr};
+
+use teloxide::{
+    dispatching::{
+        update_listeners::{webhooks, UpdateListener},
+        DefaultKey, Dispatcher,
+    },
+    error_handlers::LoggingErrorHandler,
+    Bot,
+};
+
+use crate::secrets::WebhookConfig;
+
+pub type BotDispatcher = Dispatcher<Bot, Box<dyn Error + Send + Sync>, DefaultKey>;
+
+/// Receives updates from telegram on `config.url` instead of long polling.
+pub async fn dispatch(
+    dispatcher: &mut BotDispatcher,
+    bot: Bot,
+    config: WebhookConfig,
+) -> Result<(), String> {
+    let options = options(&config)?;
+    if config.register {
+        let (listener, stop, router) = webhooks::axum_to_router(bot, options)
+            .await
+            .map_err(|e| format!("couldn't set the webhook: {e}"))?;
+        serve(dispatcher, &config.address, listener, stop, router).await
+    } else {
+        let (listener, stop, router) = webhooks::axum_no_setup(options);
+        serve(dispatcher, &config.address, listener, stop, router).await
+    }
+}
+
+fn options(config: &WebhookConfig) -> Result<webhooks::Options, String> {
+    let url = config
+        .url
+        .parse()
+        .map_err(|e| format!("invalid webhook url `{}`: {e}", config.url))?;
+    let mut options = webhooks::Options::new(config.address, url);
+    options.secret_token = config.secret_token.clone();
+    Ok(options)
+}
+
+/// Starts the http server in the background, returning the address it listens on.
+fn spawn_server(
+    address: &SocketAddr,
+    router: axum::Router,
+    stop: impl Future<Output = ()> + Send + 'static,
+) -> Result<SocketAddr, String> {
+    let server = axum::Server::try_bind(address)
+        .map_err(|e| format!("couldn't bind {address}: {e}"))?
+        .serve(router.into_make_service());
+    let local = server.local_addr();
+    let server = server.with_graceful_shutdown(stop);
+    tokio::spawn(async move {
+        if let Err(e) = server.await {
+            log::error!("webhook server error: {e}");
+        }
+    });
+    Ok(local)
+}
+
+async fn serve(
+    dispatcher: &mut BotDispatcher,
+    address: &SocketAddr,
+    listener: impl UpdateListener<Err = Infallible>,
+    stop: impl Future<Output = ()> + Send + 'static,
+    router: axum::Router,
+) -> Result<(), String> {
+    let address = spawn_server(address, router, stop)?;
+    log::info!("listening for webhook updates on {address}");
+
+    dispatcher
+        .dispatch_with_listener(
+            listener,
+            LoggingErrorHandler::with_custom_text("An error from the webhook listener"),
+        )
+        .await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+    use teloxide::dispatching::update_listeners::AsUpdateStream;
+
+    use super::*;
+
+    const UPDATE_FIXTURE: &str = include_str!("fixtures/update.json");
+
+    #[tokio::test]
+    async fn accepts_updates_with_the_secret_token() {
+        let config = WebhookConfig {
+            address: "127.0.0.1:0".parse().unwrap(),
+            url: "https://example.com/biedbot/webhook".to_string(),
+            secret_token: Some("s3cret".to_string()),
+            register: false,
+        };
+        let (mut listener, stop, router) = webhooks::axum_no_setup(options(&config).unwrap());
+        let address = spawn_server(&config.address, router, stop).unwrap();
+
+        let post = |secret: &'static str| {
+            reqwest::Client::new()
+                .post(format!("http://{address}/biedbot/webhook"))
+                .header("x-telegram-bot-api-secret-token", secret)
+                .body(UPDATE_FIXTURE)
+                .send()
+        };
+        assert_eq!(post("wrong").await.unwrap().status(), 401);
+        assert_eq!(post("s3cret").await.unwrap().status(), 200);
+
+        let update = Box::pin(listener.as_stream())
+            .next()
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(update.id, 7);
+    }
+}